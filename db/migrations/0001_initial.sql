@@ -1,4 +1,5 @@
-PRAGMA foreign_keys = ON;
+-- Initial schema. This is what db/schema.sql looked like before we
+-- switched to versioned migrations.
 
 -- mirrors the data in the config file.
 -- if at startup this collection is not defined in the config file, error out.
@@ -0,0 +1,8 @@
+-- The mediaitems sequence used to be set up by Db::set_mediaitem_sequence
+-- every time the server started. Do it once, here.
+--
+-- `sqlite_sequence` exists because `users` uses AUTOINCREMENT.
+INSERT INTO sqlite_sequence(name, seq)
+  SELECT 'mediaitems', 1000
+  WHERE NOT EXISTS (SELECT 1 FROM sqlite_sequence WHERE name = 'mediaitems');
+UPDATE sqlite_sequence SET seq = 1000 WHERE name = 'mediaitems' AND seq < 1000;
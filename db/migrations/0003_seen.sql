@@ -0,0 +1,20 @@
+-- Per-user watch progress of movies and episodes.
+CREATE TABLE seen(
+  user_id INTEGER NOT NULL,
+  mediaitem_id TEXT NOT NULL,
+
+  -- playback position and duration, in seconds.
+  position REAL NOT NULL DEFAULT 0,
+  duration REAL,
+
+  -- watched until the end.
+  completed INTEGER NOT NULL DEFAULT 0,
+
+  -- unix timestamp (ms) of the last update.
+  updated BIGINT NOT NULL,
+
+  PRIMARY KEY(user_id, mediaitem_id),
+  FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE,
+  FOREIGN KEY(mediaitem_id) REFERENCES mediaitems(id)
+);
+CREATE INDEX idx_seen_user_updated ON seen(user_id, updated);
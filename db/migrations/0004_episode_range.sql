@@ -0,0 +1,4 @@
+-- A video file can contain more than one episode (e.g. S01E04E05).
+-- `episode` is the first episode, `episode_last` the last one, or
+-- NULL if it's a single episode.
+ALTER TABLE mediaitems ADD COLUMN episode_last INTEGER;
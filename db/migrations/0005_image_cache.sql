@@ -0,0 +1,36 @@
+-- The images table is used as a cache of resized images. It was never
+-- written to before, so we can simply recreate it.
+--
+-- Originals have id == image_id, and their fileinfo is relative to
+-- the directory of the mediaitem. Resized variants have the image_id of
+-- the original, and their fileinfo is relative to the cache directory.
+DROP TABLE images;
+
+CREATE TABLE images(
+  id INTEGER PRIMARY KEY,
+  collection_id INTEGER NOT NULL,
+  mediaitem_id TEXT NOT NULL,
+
+  -- Variants have the same image_id. Original has id == image_id.
+  image_id INTEGER NOT NULL,
+
+  -- path, mtime, size.
+  fileinfo JSON NOT NULL,
+
+  -- art type (poster, thumb, fanart).
+  aspect TEXT NOT NULL,
+
+  -- dimensions
+  width INTEGER NOT NULL,
+  height INTEGER NOT NULL,
+  quality INTEGER NOT NULL DEFAULT 100,
+
+  -- unix timestamp (ms) of the last time this variant was served.
+  accessed BIGINT NOT NULL DEFAULT 0,
+
+  FOREIGN KEY(mediaitem_id) REFERENCES mediaitems(id)
+  FOREIGN KEY(image_id) REFERENCES images(id)
+);
+CREATE UNIQUE INDEX idx_images_image_id ON images(image_id, width, height, quality);
+CREATE INDEX idx_images_mediaitem_id ON images(mediaitem_id);
+CREATE INDEX idx_images_accessed ON images(accessed);
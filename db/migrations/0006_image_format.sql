@@ -0,0 +1,12 @@
+-- Resized images can be encoded as JPEG, PNG, WebP or AVIF, so the
+-- format is part of the cache key. For originals, `alpha` tells us
+-- if the image has transparency that should be kept.
+--
+-- Existing rows don't know about alpha. It's only a cache, so start over.
+DELETE FROM images;
+
+ALTER TABLE images ADD COLUMN format TEXT NOT NULL DEFAULT 'jpg';
+ALTER TABLE images ADD COLUMN alpha BOOLEAN NOT NULL DEFAULT 0;
+
+DROP INDEX idx_images_image_id;
+CREATE UNIQUE INDEX idx_images_image_id ON images(image_id, width, height, quality, format);
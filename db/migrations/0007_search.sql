@@ -0,0 +1,49 @@
+-- Full-text search index of mediaitems.
+--
+-- The text to index is collected by the mediaitems_search view, and the
+-- triggers below keep the index in sync with the mediaitems table.
+-- Diacritics are removed, so that "cafe" finds "Café".
+CREATE VIRTUAL TABLE mediaitems_fts USING fts5(
+  mediaitem_id UNINDEXED,
+  title,
+  originaltitle,
+  plot,
+  tagline,
+  actors,
+  directors,
+  studios,
+  tokenize = 'unicode61 remove_diacritics 2',
+  prefix = '2 3'
+);
+
+CREATE VIEW mediaitems_search AS
+  SELECT i.id AS mediaitem_id,
+         i.title,
+         json_extract(i.nfo_info, '$.originaltitle') AS originaltitle,
+         json_extract(i.nfo_info, '$.plot') AS plot,
+         json_extract(i.nfo_info, '$.tagline') AS tagline,
+         (SELECT group_concat(json_extract(a.value, '$.name'), ', ')
+          FROM json_each(i.nfo_info, '$.actors') a) AS actors,
+         (SELECT group_concat(d.value, ', ')
+          FROM json_each(i.nfo_info, '$.directors') d) AS directors,
+         (SELECT group_concat(s.value, ', ')
+          FROM json_each(i.nfo_info, '$.studios') s) AS studios
+  FROM mediaitems i
+  WHERE i.deleted = 0 AND i.type IN ('movie', 'tvshow', 'episode');
+
+CREATE TRIGGER mediaitems_fts_insert AFTER INSERT ON mediaitems BEGIN
+  INSERT INTO mediaitems_fts
+    SELECT * FROM mediaitems_search WHERE mediaitem_id = new.id;
+END;
+
+CREATE TRIGGER mediaitems_fts_update AFTER UPDATE OF title, nfo_info, deleted ON mediaitems BEGIN
+  DELETE FROM mediaitems_fts WHERE mediaitem_id = old.id;
+  INSERT INTO mediaitems_fts
+    SELECT * FROM mediaitems_search WHERE mediaitem_id = new.id;
+END;
+
+CREATE TRIGGER mediaitems_fts_delete AFTER DELETE ON mediaitems BEGIN
+  DELETE FROM mediaitems_fts WHERE mediaitem_id = old.id;
+END;
+
+INSERT INTO mediaitems_fts SELECT * FROM mediaitems_search;
@@ -0,0 +1,4 @@
+-- Collection listings filter on collection and type, and look up
+-- the episodes of every tvshow.
+CREATE INDEX idx_mediaitems_collection ON mediaitems(collection_id, type);
+CREATE INDEX idx_mediaitems_tvshow_id ON mediaitems(tvshow_id);
@@ -0,0 +1,28 @@
+-- Change stamp of mediaitems, for incremental sync.
+--
+-- `lastmodified` is the mtime of the newest file of an item. That can
+-- go backwards, and it does not change when an item is marked as deleted.
+-- So keep a separate stamp, a unix timestamp in ms, that the triggers
+-- below bump on every insert and update. It is unique and always
+-- increasing, even if the clock is not.
+ALTER TABLE mediaitems ADD COLUMN changed BIGINT NOT NULL DEFAULT 0;
+CREATE INDEX idx_mediaitems_changed ON mediaitems(changed);
+
+CREATE TRIGGER mediaitems_changed_insert AFTER INSERT ON mediaitems BEGIN
+  UPDATE mediaitems
+  SET changed = MAX(CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER),
+                    (SELECT COALESCE(MAX(changed), 0) + 1 FROM mediaitems))
+  WHERE id = new.id;
+END;
+
+-- Only when `changed` was not set, so this does not trigger itself.
+CREATE TRIGGER mediaitems_changed_update AFTER UPDATE ON mediaitems
+WHEN new.changed = old.changed BEGIN
+  UPDATE mediaitems
+  SET changed = MAX(CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER),
+                    (SELECT COALESCE(MAX(changed), 0) + 1 FROM mediaitems))
+  WHERE id = new.id;
+END;
+
+-- Give every existing item a stamp.
+UPDATE mediaitems SET changed = changed;
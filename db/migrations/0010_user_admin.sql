@@ -0,0 +1,5 @@
+-- Admins can manage all users, other users only their own account.
+--
+-- Existing users are not made admin. Use `notflix-backend admin` to
+-- promote a user.
+ALTER TABLE users ADD COLUMN admin BOOLEAN NOT NULL DEFAULT 0;
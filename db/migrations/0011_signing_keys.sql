@@ -0,0 +1,11 @@
+-- Keys to sign media tokens and playback URLs with.
+--
+-- The newest key is used to sign. Older keys are kept until all tokens
+-- signed with them have expired, so that tokens survive a key rotation
+-- and a server restart.
+CREATE TABLE signing_keys(
+  id INTEGER PRIMARY KEY,
+  key BLOB NOT NULL,
+  -- unix timestamp (ms).
+  created BIGINT NOT NULL
+);
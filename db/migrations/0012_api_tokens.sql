@@ -0,0 +1,18 @@
+-- Personal access tokens, for scripts and devices that can't log in.
+--
+-- Only the SHA-256 hash of the token is stored. `scope` is 'read' or
+-- 'write'. Times are unix timestamps in ms, `expires` NULL means never.
+CREATE TABLE api_tokens(
+  id INTEGER PRIMARY KEY,
+  user_id INTEGER NOT NULL,
+  name TEXT NOT NULL,
+  hash TEXT NOT NULL,
+  scope TEXT NOT NULL,
+  created BIGINT NOT NULL,
+  expires BIGINT,
+  last_used BIGINT,
+
+  FOREIGN KEY(user_id) REFERENCES users(id)
+);
+CREATE UNIQUE INDEX idx_api_tokens_hash ON api_tokens(hash);
+CREATE INDEX idx_api_tokens_user_id ON api_tokens(user_id);
@@ -0,0 +1,7 @@
+-- Remember which client a session belongs to, so that users can see
+-- where they are logged in and revoke sessions.
+ALTER TABLE sessions ADD COLUMN device TEXT;
+ALTER TABLE sessions ADD COLUMN user_agent TEXT;
+ALTER TABLE sessions ADD COLUMN remote_addr TEXT;
+CREATE INDEX idx_sessions_user_id ON sessions(user_id);
+CREATE INDEX idx_sessions_updated ON sessions(updated);
@@ -0,0 +1,15 @@
+-- Audit log of successful and failed logins.
+--
+-- `time` is a unix timestamp in ms. `user_id` is NULL if the username
+-- does not exist.
+CREATE TABLE audit_log(
+  id INTEGER PRIMARY KEY,
+  time BIGINT NOT NULL,
+  event TEXT NOT NULL,
+  username TEXT NOT NULL,
+  user_id INTEGER,
+  device TEXT,
+  user_agent TEXT,
+  remote_addr TEXT
+);
+CREATE INDEX idx_audit_log_time ON audit_log(time);
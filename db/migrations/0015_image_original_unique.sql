@@ -0,0 +1,17 @@
+-- There is only one original per mediaitem and path. While an original
+-- is being inserted its image_id is 0, until it is set to its own id.
+--
+-- Remove duplicate originals and their variants first, keeping the oldest.
+DELETE FROM images
+WHERE image_id IN (
+  SELECT o.id FROM images o
+  WHERE o.id = o.image_id AND EXISTS (
+    SELECT 1 FROM images p
+    WHERE p.id = p.image_id AND p.id < o.id
+      AND p.mediaitem_id = o.mediaitem_id
+      AND json_extract(p.fileinfo, '$.path') = json_extract(o.fileinfo, '$.path')
+  )
+);
+
+CREATE UNIQUE INDEX idx_images_original ON images(mediaitem_id, json_extract(fileinfo, '$.path'))
+WHERE image_id = id OR image_id = 0;
@@ -1,23 +1,40 @@
-use poem::{Body, Request, Result};
+use futures_util::stream::BoxStream;
+use poem::{error::NotFoundError, Body, Request, Result};
 use poem_openapi::{
     param::{Path, Query},
-    payload::{Binary, Json, Response},
+    payload::{Binary, EventStream, Json, Response},
     Object, OpenApi, Tags,
 };
 
+use crate::events::Event;
+use crate::models::{FacetType, ListFilter, SortBy};
 use crate::server::{SessionFC, SessionFK, SharedState};
 use crate::util::Id;
 
-//mod collection;
-//mod image;
-//mod movie;
-//mod tvshow;
+mod apitoken;
+mod collection;
+mod events;
+mod image;
+mod movie;
+mod search;
+mod seen;
+mod session;
+mod subtitle;
+mod sync;
+mod token;
+mod tvshow;
 mod user;
 
-//use self::image::*;
-//use collection::*;
-//use movie::*;
-//use tvshow::*;
+use self::image::*;
+use apitoken::*;
+use collection::*;
+use movie::*;
+use search::*;
+use seen::*;
+use session::*;
+use sync::*;
+use token::*;
+use tvshow::*;
 use user::*;
 
 #[derive(Tags)]
@@ -30,12 +47,18 @@ enum ApiTags {
     Media,
     /// Operations on users.
     User,
+    /// Per-user watch progress.
+    Progress,
 }
 
 #[derive(Object)]
 pub struct Authenticate {
+    #[oai(validator(max_length = 64))]
     pub username: String,
     pub password: String,
+    /// Name of this device, shown in the list of sessions.
+    #[oai(validator(max_length = 64))]
+    pub device: Option<String>,
 }
 
 pub struct Api {
@@ -49,6 +72,9 @@ impl Api {
     }
 
     /// Authenticate to get a session key
+    ///
+    /// After a few failed logins for a username or from an address, further
+    /// attempts are refused for a while with status 429 and `Retry-After`.
     #[oai(path = "/auth/login", method = "post", tag = "ApiTags::Authorization")]
     async fn api_login(
         &self,
@@ -69,73 +95,425 @@ impl Api {
         let resp = self.logout(session.0, req).await?;
         Ok(resp)
     }
-    /*
-        /// List collections.
-        #[oai(path = "/collections", method = "get", tag = "ApiTags::Collection")]
-        async fn api_get_collections(&self, _session: SessionFK) -> Result<GetCollectionsResponse> {
-            let res = self.get_collections().await?;
-            Ok(res)
-        }
-
-        /// Get thumbnails of a collection.
-        #[oai(path = "/collection/:collection_id/thumbs", method = "get", tag = "ApiTags::Collection")]
-        async fn api_get_thumbs(
-            &self,
-            _session: SessionFK,
-            collection_id: Path<i64>,
-        ) -> Result<GetThumbsResponse> {
-            let res = self.get_thumbs(collection_id.0).await?;
-            Ok(res)
-        }
-
-        /// Find tvshow by id.
-        #[oai(path = "/tvshow/:collection_id/:tvshow_id", method = "get", tag = "ApiTags::Media")]
-        async fn api_get_tvshow(
-            &self,
-            _session: SessionFK,
-            collection_id: Path<i64>,
-            tvshow_id: Path<String>,
-        ) -> Result<GetTVShowResponse> {
-            let res = self.get_tvshow(collection_id.0, Id::from_str(&tvshow_id.0)?).await?;
-            Ok(res)
-        }
-
-        /// Find movie by id.
-        #[oai(path = "/movie/:collection_id/:movie_id", method = "get", tag = "ApiTags::Media")]
-        async fn api_get_movie(
-            &self,
-            _session: SessionFK,
-            collection_id: Path<i64>,
-            movie_id: Path<String>,
-        ) -> Result<GetMovieResponse> {
-            let res = self.get_movie(collection_id.0, Id::from_str(&movie_id.0)?).await?;
-            Ok(res)
-        }
-
-        /// Retrieve image.
-        #[oai(
-            path = "/image/:collection_id/:mediaitem_id/:image_id",
-            method = "get",
-            tag = "ApiTags::Media"
-        )]
-        async fn api_get_image(
-            &self,
-            _session: SessionFC,
-            collection_id: Path<u32>,
-            mediaitem_id: Path<String>,
-            image_id: Path<i64>,
-            w: Query<Option<u32>>,
-            h: Query<Option<u32>>,
-            q: Query<Option<u32>>,
-            req: &Request,
-        ) -> Result<Response<Binary<Body>>> {
-            let whq = ImageOpts { width: w.0, height: h.0, quality: q.0 };
-            let mid = Id::from_str(&mediaitem_id.0)?;
-            let res = self.get_image(collection_id.0, mid, image_id.0, whq, req).await?;
-            Ok(res)
-        }
-    */
+
+    /// List your active sessions.
+    #[oai(path = "/sessions", method = "get", tag = "ApiTags::Authorization")]
+    async fn api_get_sessions(&self, session: SessionFK) -> Result<GetSessionsResponse> {
+        let user_id = session.0.user_id;
+        let res = self.get_sessions(session.0, user_id).await?;
+        Ok(res)
+    }
+
+    /// Revoke one of your sessions.
+    #[oai(path = "/sessions/:session_id", method = "delete", tag = "ApiTags::Authorization")]
+    async fn api_revoke_session(
+        &self,
+        session: SessionFK,
+        session_id: Path<i64>,
+    ) -> Result<RevokeSessionResponse> {
+        let user_id = session.0.user_id;
+        let res = self.revoke_session(session.0, user_id, session_id.0).await?;
+        Ok(res)
+    }
+
+    /// Revoke all your sessions, except the current one.
+    #[oai(path = "/sessions", method = "delete", tag = "ApiTags::Authorization")]
+    async fn api_revoke_sessions(&self, session: SessionFK) -> Result<RevokeSessionsResponse> {
+        let user_id = session.0.user_id;
+        let res = self.revoke_sessions(session.0, user_id).await?;
+        Ok(res)
+    }
+
+    /// Create a personal access token.
+    ///
+    /// For scripts and devices. The token can be used instead of a session
+    /// id, and does not time out. A `read` token can only be used for GET
+    /// requests. Tokens can not be created with a token.
+    #[oai(path = "/tokens", method = "post", tag = "ApiTags::Authorization")]
+    async fn api_create_api_token(
+        &self,
+        session: SessionFK,
+        token: Json<CreateApiToken>,
+    ) -> Result<CreateApiTokenResponse> {
+        let res = self.create_api_token(session.0, token.0).await?;
+        Ok(res)
+    }
+
+    /// List your personal access tokens.
+    #[oai(path = "/tokens", method = "get", tag = "ApiTags::Authorization")]
+    async fn api_get_api_tokens(&self, session: SessionFK) -> Result<GetApiTokensResponse> {
+        let res = self.get_api_tokens(session.0).await?;
+        Ok(res)
+    }
+
+    /// Revoke a personal access token.
+    #[oai(path = "/tokens/:token_id", method = "delete", tag = "ApiTags::Authorization")]
+    async fn api_delete_api_token(
+        &self,
+        session: SessionFK,
+        token_id: Path<i64>,
+    ) -> Result<DeleteApiTokenResponse> {
+        let res = self.delete_api_token(session.0, token_id.0).await?;
+        Ok(res)
+    }
+
+    /// List collections.
+    #[oai(path = "/collections", method = "get", tag = "ApiTags::Collection")]
+    async fn api_get_collections(&self, _session: SessionFK) -> Result<GetCollectionsResponse<'_>> {
+        let res = self.get_collections().await?;
+        Ok(res)
+    }
+
+    /// Get thumbnails of a collection.
+    ///
+    /// All filters are optional. `rating` is a minimum on a scale of 0-10,
+    /// `resolution` is the minimum resolution of the video (720, 1080, 2160),
+    /// where a video that is wider than 16:9 counts by its width.
+    /// A tvshow is `watched` when all its episodes are. Without `limit`,
+    /// all items are returned. The total number of items that matched
+    /// is returned in the `X-Total-Count` header.
+    #[oai(path = "/collection/:collection_id/thumbs", method = "get", tag = "ApiTags::Collection")]
+    #[allow(clippy::too_many_arguments)]
+    async fn api_get_thumbs(
+        &self,
+        session: SessionFK,
+        collection_id: Path<i64>,
+        genre: Query<Option<String>>,
+        year_from: Query<Option<u32>>,
+        year_to: Query<Option<u32>>,
+        mpaa: Query<Option<String>>,
+        country: Query<Option<String>>,
+        studio: Query<Option<String>>,
+        rating: Query<Option<f64>>,
+        resolution: Query<Option<u32>>,
+        watched: Query<Option<bool>>,
+        sort: Query<Option<SortBy>>,
+        desc: Query<Option<bool>>,
+        offset: Query<Option<u32>>,
+        limit: Query<Option<u32>>,
+    ) -> Result<GetThumbsResponse> {
+        let filter = ListFilter {
+            genre: genre.0,
+            year_from: year_from.0,
+            year_to: year_to.0,
+            mpaa: mpaa.0,
+            country: country.0,
+            studio: studio.0,
+            rating: rating.0,
+            resolution: resolution.0,
+            watched: watched.0,
+            sort: sort.0.unwrap_or_default(),
+            desc: desc.0.unwrap_or(false),
+            offset: offset.0.unwrap_or(0),
+            limit: limit.0,
+            ..ListFilter::default()
+        };
+        let res = self.get_thumbs(session.0, collection_id.0, filter).await?;
+        Ok(res)
+    }
+
+    /// List the genres, studios, countries, directors or actors of a collection.
+    ///
+    /// Sorted by name, with the number of movies or tvshows for each.
+    #[oai(
+        path = "/collection/:collection_id/facets/:facet",
+        method = "get",
+        tag = "ApiTags::Collection"
+    )]
+    async fn api_get_facets(
+        &self,
+        _session: SessionFK,
+        collection_id: Path<i64>,
+        facet: Path<FacetType>,
+    ) -> Result<GetFacetsResponse> {
+        let res = self.get_facets(collection_id.0, facet.0).await?;
+        Ok(res)
+    }
+
+    /// Get thumbnails of the items with one genre, studio, country, director or actor.
+    ///
+    /// The total number of items is returned in the `X-Total-Count` header.
+    #[oai(
+        path = "/collection/:collection_id/facets/:facet/items",
+        method = "get",
+        tag = "ApiTags::Collection"
+    )]
+    #[allow(clippy::too_many_arguments)]
+    async fn api_get_facet_items(
+        &self,
+        session: SessionFK,
+        collection_id: Path<i64>,
+        facet: Path<FacetType>,
+        name: Query<String>,
+        sort: Query<Option<SortBy>>,
+        desc: Query<Option<bool>>,
+        offset: Query<Option<u32>>,
+        limit: Query<Option<u32>>,
+    ) -> Result<GetThumbsResponse> {
+        let mut filter = ListFilter {
+            sort: sort.0.unwrap_or_default(),
+            desc: desc.0.unwrap_or(false),
+            offset: offset.0.unwrap_or(0),
+            limit: limit.0,
+            ..ListFilter::default()
+        };
+        filter.set_facet(facet.0, name.0);
+        let res = self.get_thumbs(session.0, collection_id.0, filter).await?;
+        Ok(res)
+    }
+
+    /// Start a rescan of a collection.
+    #[oai(path = "/collection/:collection_id/scan", method = "post", tag = "ApiTags::Collection")]
+    async fn api_scan_collection(
+        &self,
+        _session: SessionFK,
+        collection_id: Path<i64>,
+    ) -> Result<ScanCollectionResponse> {
+        let res = self.scan_collection(collection_id.0).await?;
+        Ok(res)
+    }
+
+    /// Get the status of the last rescan of a collection.
+    #[oai(path = "/collection/:collection_id/scan", method = "get", tag = "ApiTags::Collection")]
+    async fn api_get_scan_status(
+        &self,
+        _session: SessionFK,
+        collection_id: Path<i64>,
+    ) -> Result<GetScanStatusResponse> {
+        let res = self.get_scan_status(collection_id.0).await?;
+        Ok(res)
+    }
+
+    /// Find tvshow by id.
+    #[oai(path = "/tvshow/:collection_id/:tvshow_id", method = "get", tag = "ApiTags::Media")]
+    async fn api_get_tvshow(
+        &self,
+        _session: SessionFK,
+        collection_id: Path<i64>,
+        tvshow_id: Path<String>,
+    ) -> Result<GetTVShowResponse> {
+        let res = self.get_tvshow(collection_id.0, Id::from_str(&tvshow_id.0)?).await?;
+        Ok(res)
+    }
+
+    /// Find movie by id.
+    #[oai(path = "/movie/:collection_id/:movie_id", method = "get", tag = "ApiTags::Media")]
+    async fn api_get_movie(
+        &self,
+        _session: SessionFK,
+        collection_id: Path<i64>,
+        movie_id: Path<String>,
+    ) -> Result<GetMovieResponse> {
+        let res = self.get_movie(collection_id.0, Id::from_str(&movie_id.0)?).await?;
+        Ok(res)
+    }
+
+    /// Search movies, tvshows and episodes.
+    ///
+    /// Searches titles, plots, actors, directors and studios. Every word
+    /// is a prefix, and all words must match. Accents are ignored.
+    #[oai(path = "/search", method = "get", tag = "ApiTags::Media")]
+    async fn api_search(
+        &self,
+        _session: SessionFK,
+        q: Query<String>,
+        limit: Query<Option<u32>>,
+    ) -> Result<SearchResponse> {
+        let res = self.search(&q.0, limit.0).await?;
+        Ok(res)
+    }
+
+    /// Get the items that changed since the last sync.
+    ///
+    /// Returns movies, tvshows and episodes that were added, updated or
+    /// deleted after `token`, and a new token for the next call. Without
+    /// a token, `since` (a unix timestamp in ms) is used. Without either,
+    /// all items are returned. If `more` is set, call again with the new
+    /// token to get the next batch.
+    #[oai(path = "/sync", method = "get", tag = "ApiTags::Media")]
+    async fn api_sync(
+        &self,
+        _session: SessionFK,
+        token: Query<Option<String>>,
+        since: Query<Option<i64>>,
+        collection_id: Query<Option<i64>>,
+        limit: Query<Option<u32>>,
+    ) -> Result<SyncResponse> {
+        let res = self.sync(token.0.as_deref(), since.0, collection_id.0, limit.0).await?;
+        Ok(res)
+    }
+
+    /// Stream of events.
+    ///
+    /// Server-sent events, one JSON object per event. Sent when a movie,
+    /// tvshow or episode is added, updated or deleted, when a scan of a
+    /// collection starts or finishes, and when the watch progress of
+    /// an item is changed by another session of the same user. Refetch
+    /// the item by `id` and `type`. After a `resync` event, use `/sync`.
+    ///
+    /// Authenticates with a cookie, so that `EventSource` can be used.
+    #[oai(path = "/events", method = "get", tag = "ApiTags::Media")]
+    async fn api_events(&self, session: SessionFC) -> EventStream<BoxStream<'static, Event>> {
+        self.events(session.0)
+    }
+
+    /// Get a token to stream media.
+    ///
+    /// For devices that can't send the session cookie, such as a Chromecast.
+    /// The token is valid for `path` in the collection and everything below
+    /// it, for a few hours. Add it as `?token=` to the `/media` URL.
+    #[oai(path = "/media-token/:collection_id", method = "get", tag = "ApiTags::Media")]
+    async fn api_media_token(
+        &self,
+        session: SessionFK,
+        collection_id: Path<u32>,
+        path: Query<String>,
+    ) -> Result<MediaTokenResponse> {
+        let res = self.media_token(session.0, collection_id.0, &path.0).await?;
+        Ok(res)
+    }
+
+    /// Get a signed URL to play a movie or episode.
+    ///
+    /// For cast devices and external players that can't send the session
+    /// cookie. The URL is valid for a few hours. With `bind_ip`, it only
+    /// works for requests from the same IP address as this request.
+    #[oai(path = "/playback-url/:mediaitem_id", method = "get", tag = "ApiTags::Media")]
+    async fn api_playback_url(
+        &self,
+        session: SessionFK,
+        mediaitem_id: Path<String>,
+        bind_ip: Query<Option<bool>>,
+        req: &Request,
+    ) -> Result<PlaybackUrlResponse> {
+        let id = Id::from_str(&mediaitem_id.0)?;
+        let ip = match bind_ip.0 {
+            Some(true) => req.remote_addr().as_socket_addr().map(|addr| addr.ip()),
+            _ => None,
+        };
+        let res = self.playback_url(session.0, id, ip).await?;
+        Ok(res)
+    }
+
+    /// Retrieve image.
+    ///
+    /// The image is named `<image_id>.<ext>`, see the `path` of a thumb.
+    ///
+    /// Resized images are encoded as WebP or AVIF if the `Accept` header
+    /// says that the client supports it. The format can also be set with
+    /// `fmt` (`jpg`, `png`, `webp`, `avif`).
+    #[oai(path = "/image/:mediaitem_id/:image", method = "get", tag = "ApiTags::Media")]
+    #[allow(clippy::too_many_arguments)]
+    async fn api_get_image(
+        &self,
+        _session: SessionFC,
+        mediaitem_id: Path<String>,
+        image: Path<String>,
+        w: Query<Option<u32>>,
+        h: Query<Option<u32>>,
+        q: Query<Option<u32>>,
+        fmt: Query<Option<String>>,
+        req: &Request,
+    ) -> Result<Response<Binary<Body>>> {
+        let whq = ImageOpts {
+            width: w.0,
+            height: h.0,
+            quality: q.0,
+            format: fmt.0,
+        };
+        let mid = Id::from_str(&mediaitem_id.0)?;
+        let image_id =
+            image.0.split('.').next().unwrap_or("").parse::<i64>().map_err(|_| NotFoundError)?;
+        let res = self.get_image(mid, image_id, whq, req).await?;
+        Ok(res)
+    }
+
+    /// Retrieve subtitles in WebVTT format.
+    ///
+    /// The subtitle is named `<subtitle_id>.vtt`, see the `path` of a subtitle.
+    #[oai(path = "/subtitle/:mediaitem_id/:subtitle", method = "get", tag = "ApiTags::Media")]
+    async fn api_get_subtitle(
+        &self,
+        _session: SessionFC,
+        mediaitem_id: Path<String>,
+        subtitle: Path<String>,
+    ) -> Result<Response<Binary<Vec<u8>>>> {
+        let mid = Id::from_str(&mediaitem_id.0)?;
+        let subtitle_id =
+            subtitle.0.split('.').next().unwrap_or("").parse::<u32>().map_err(|_| NotFoundError)?;
+        let res = self.get_subtitle(mid, subtitle_id).await?;
+        Ok(res)
+    }
+
+    /// Get watch progress of a movie or episode.
+    #[oai(path = "/progress/:mediaitem_id", method = "get", tag = "ApiTags::Progress")]
+    async fn api_get_progress(
+        &self,
+        session: SessionFK,
+        mediaitem_id: Path<String>,
+    ) -> Result<GetProgressResponse> {
+        let res = self.get_progress(session.0, Id::from_str(&mediaitem_id.0)?).await?;
+        Ok(res)
+    }
+
+    /// Set watch progress of a movie or episode.
+    #[oai(path = "/progress/:mediaitem_id", method = "put", tag = "ApiTags::Progress")]
+    async fn api_update_progress(
+        &self,
+        session: SessionFK,
+        mediaitem_id: Path<String>,
+        progress: Json<UpdateProgress>,
+    ) -> Result<UpdateProgressResponse> {
+        let id = Id::from_str(&mediaitem_id.0)?;
+        let res = self.update_progress(session.0, id, progress.0).await?;
+        Ok(res)
+    }
+
+    /// Mark all episodes of a tvshow, or of one season, as seen or unseen.
+    #[oai(path = "/seen/tvshow/:tvshow_id", method = "put", tag = "ApiTags::Progress")]
+    async fn api_mark_tvshow_seen(
+        &self,
+        session: SessionFK,
+        tvshow_id: Path<String>,
+        mark: Json<MarkSeen>,
+    ) -> Result<MarkSeenResponse> {
+        let id = Id::from_str(&tvshow_id.0)?;
+        let res = self.mark_tvshow_seen(session.0, id, mark.0).await?;
+        Ok(res)
+    }
+
+    /// List watch progress updated since `since` (unix timestamp in ms).
+    #[oai(path = "/seen", method = "get", tag = "ApiTags::Progress")]
+    async fn api_get_seen(
+        &self,
+        session: SessionFK,
+        since: Query<Option<i64>>,
+    ) -> Result<GetSeenResponse> {
+        let res = self.get_seen(session.0, since.0).await?;
+        Ok(res)
+    }
+
+    /// Partially watched movies and episodes, most recent first.
+    #[oai(path = "/continue-watching", method = "get", tag = "ApiTags::Progress")]
+    async fn api_get_continue_watching(
+        &self,
+        session: SessionFK,
+        limit: Query<Option<u32>>,
+    ) -> Result<GetSeenItemsResponse> {
+        let res = self.get_continue_watching(session.0, limit.0).await?;
+        Ok(res)
+    }
+
+    /// The next episode to watch, for every tvshow the user is watching.
+    #[oai(path = "/next-up", method = "get", tag = "ApiTags::Progress")]
+    async fn api_get_next_up(&self, session: SessionFK) -> Result<GetSeenItemsResponse> {
+        let res = self.get_next_up(session.0).await?;
+        Ok(res)
+    }
+
     /// Create a new user
+    ///
+    /// Only admins can create users.
     #[oai(path = "/users", method = "post", tag = "ApiTags::User")]
     async fn api_create_user(
         &self,
@@ -147,6 +525,8 @@ impl Api {
     }
 
     /// Delete user by id
+    ///
+    /// Only admins can delete users.
     #[oai(path = "/users/:user_id", method = "delete", tag = "ApiTags::User")]
     async fn api_delete_user(
         &self,
@@ -158,6 +538,9 @@ impl Api {
     }
 
     /// Update user by id
+    ///
+    /// Users can update their own account, admins can update all accounts.
+    /// Only admins can change the `admin` flag.
     #[oai(path = "/users/:user_id", method = "put", tag = "ApiTags::User")]
     async fn api_update_user(
         &self,
@@ -170,6 +553,8 @@ impl Api {
     }
 
     /// Find user by name
+    ///
+    /// Users can only find themselves, admins can find all users.
     #[oai(path = "/users/:user_id", method = "get", tag = "ApiTags::User")]
     async fn api_find_user(
         &self,
@@ -181,9 +566,59 @@ impl Api {
     }
 
     /// Get all users.
+    ///
+    /// Only admins can list users.
     #[oai(path = "/users", method = "get", tag = "ApiTags::User")]
     async fn api_get_users(&self, session: SessionFK) -> Result<GetUsersResponse> {
         let res = self.get_users(session.0).await?;
         Ok(res)
     }
+
+    /// Get the audit log of logins (admin only).
+    #[oai(path = "/audit-log", method = "get", tag = "ApiTags::User")]
+    async fn api_get_audit_log(
+        &self,
+        session: SessionFK,
+        offset: Query<Option<u32>>,
+        limit: Query<Option<u32>>,
+    ) -> Result<GetAuditLogResponse> {
+        let offset = offset.0.unwrap_or(0);
+        let limit = limit.0.unwrap_or(100).min(1000);
+        let res = self.get_audit_log(session.0, offset, limit).await?;
+        Ok(res)
+    }
+
+    /// List the sessions of a user (admin, or your own account).
+    #[oai(path = "/users/:user_id/sessions", method = "get", tag = "ApiTags::User")]
+    async fn api_get_user_sessions(
+        &self,
+        session: SessionFK,
+        user_id: Path<i64>,
+    ) -> Result<GetSessionsResponse> {
+        let res = self.get_sessions(session.0, user_id.0).await?;
+        Ok(res)
+    }
+
+    /// Revoke a session of a user (admin, or your own account).
+    #[oai(path = "/users/:user_id/sessions/:session_id", method = "delete", tag = "ApiTags::User")]
+    async fn api_revoke_user_session(
+        &self,
+        session: SessionFK,
+        user_id: Path<i64>,
+        session_id: Path<i64>,
+    ) -> Result<RevokeSessionResponse> {
+        let res = self.revoke_session(session.0, user_id.0, session_id.0).await?;
+        Ok(res)
+    }
+
+    /// Revoke all sessions of a user (admin, or your own account).
+    #[oai(path = "/users/:user_id/sessions", method = "delete", tag = "ApiTags::User")]
+    async fn api_revoke_user_sessions(
+        &self,
+        session: SessionFK,
+        user_id: Path<i64>,
+    ) -> Result<RevokeSessionsResponse> {
+        let res = self.revoke_sessions(session.0, user_id.0).await?;
+        Ok(res)
+    }
 }
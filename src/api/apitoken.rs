@@ -0,0 +1,108 @@
+use std::time::SystemTime;
+
+use anyhow::Result;
+use poem_openapi::{payload::Json, ApiResponse, Object};
+
+use super::Api;
+use crate::models::{ApiToken, Session, TokenScope};
+use crate::util::SystemTimeToUnixTime;
+
+/// Create token schema
+#[derive(Debug, Object, Clone)]
+pub struct CreateApiToken {
+    /// Name, to recognize the token by.
+    #[oai(validator(min_length = 1, max_length = 64))]
+    pub name: String,
+    /// Scope. Defaults to `read`.
+    pub scope: Option<TokenScope>,
+    /// Time the token expires (unix timestamp in ms). Never if not set.
+    pub expires: Option<i64>,
+}
+
+/// A new token.
+#[derive(Object)]
+pub struct NewApiToken {
+    /// Send in the `X-Session-Id` header. It can not be retrieved again.
+    pub token: String,
+    /// The token.
+    pub info: ApiToken,
+}
+
+#[derive(ApiResponse)]
+pub enum CreateApiTokenResponse {
+    /// Token successfully created.
+    #[oai(status = 200)]
+    Ok(Json<NewApiToken>),
+
+    /// The expiry time is in the past.
+    #[oai(status = 400)]
+    BadExpires,
+
+    /// Tokens can only be created from a login session.
+    #[oai(status = 403)]
+    Forbidden,
+}
+
+#[derive(ApiResponse)]
+pub enum GetApiTokensResponse {
+    /// List of tokens.
+    #[oai(status = 200)]
+    Ok(Json<Vec<ApiToken>>),
+}
+
+#[derive(ApiResponse)]
+pub enum DeleteApiTokenResponse {
+    /// Token successfully revoked.
+    #[oai(status = 200)]
+    Ok,
+
+    /// Token not found.
+    #[oai(status = 404)]
+    NotFound,
+}
+
+impl Api {
+    pub async fn create_api_token(
+        &self,
+        session: Session,
+        token: CreateApiToken,
+    ) -> Result<CreateApiTokenResponse> {
+        if session.token_id.is_some() {
+            return Ok(CreateApiTokenResponse::Forbidden);
+        }
+        if let Some(expires) = token.expires {
+            if expires <= SystemTime::now().unixtime_ms() {
+                return Ok(CreateApiTokenResponse::BadExpires);
+            }
+        }
+        let scope = token.scope.unwrap_or(TokenScope::Read);
+
+        let mut txn = self.state.db.handle.begin().await?;
+        let (info, token) =
+            ApiToken::create(&mut txn, session.user_id, &token.name, scope, token.expires).await?;
+        txn.commit().await?;
+        Ok(CreateApiTokenResponse::Ok(Json(NewApiToken { token, info })))
+    }
+
+    pub async fn get_api_tokens(&self, session: Session) -> Result<GetApiTokensResponse> {
+        let mut txn = self.state.db.handle.begin().await?;
+        let tokens = ApiToken::list(&mut txn, session.user_id).await?;
+        Ok(GetApiTokensResponse::Ok(Json(tokens)))
+    }
+
+    pub async fn delete_api_token(
+        &self,
+        session: Session,
+        token_id: i64,
+    ) -> Result<DeleteApiTokenResponse> {
+        // Admins can revoke the tokens of all users.
+        let user_id = (!session.admin).then_some(session.user_id);
+        let mut txn = self.state.db.handle.begin().await?;
+        let resp = match ApiToken::delete(&mut txn, token_id, user_id).await? {
+            true => DeleteApiTokenResponse::Ok,
+            false => DeleteApiTokenResponse::NotFound,
+        };
+        txn.commit().await?;
+        Ok(resp)
+    }
+}
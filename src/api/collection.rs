@@ -1,10 +1,11 @@
 use super::Api;
-use crate::models;
+use crate::models::{self, Facet, FacetType, ListFilter, Session};
 use crate::util::Id;
 use anyhow::Result;
 use poem_openapi::{payload::Json, ApiResponse, Object};
 
 pub use crate::collections::Collection;
+use crate::scanner::ScanStatus;
 
 #[derive(ApiResponse)]
 pub enum GetCollectionsResponse<'a> {
@@ -33,15 +34,57 @@ pub struct MediaItem {
 pub enum GetThumbsResponse {
     /// Return when the collections are listed.
     #[oai(status = 200)]
-    Ok(Json<Vec<MediaItem>>),
+    Ok(
+        Json<Vec<MediaItem>>,
+        /// Total number of items that matched, on all pages.
+        #[oai(header = "X-Total-Count")]
+        u64,
+    ),
 
     /// Return when there are no collections.
     #[oai(status = 404)]
     NotFound,
 }
 
+#[derive(ApiResponse)]
+pub enum GetFacetsResponse {
+    /// Genres, studios, countries, directors or actors.
+    #[oai(status = 200)]
+    Ok(Json<Vec<Facet>>),
+
+    /// Return when the collection was not found.
+    #[oai(status = 404)]
+    NotFound,
+}
+
+#[derive(ApiResponse)]
+pub enum ScanCollectionResponse {
+    /// The scan was started.
+    #[oai(status = 202)]
+    Started(Json<ScanStatus>),
+
+    /// A scan of this collection is already running.
+    #[oai(status = 409)]
+    Running(Json<ScanStatus>),
+
+    /// Return when the collection was not found.
+    #[oai(status = 404)]
+    NotFound,
+}
+
+#[derive(ApiResponse)]
+pub enum GetScanStatusResponse {
+    /// Status of the last scan.
+    #[oai(status = 200)]
+    Ok(Json<ScanStatus>),
+
+    /// Return when the collection was not found.
+    #[oai(status = 404)]
+    NotFound,
+}
+
 impl Api {
-    pub async fn get_collections(&self) -> Result<GetCollectionsResponse> {
+    pub async fn get_collections(&self) -> Result<GetCollectionsResponse<'_>> {
         if self.state.config.collections.is_empty() {
             Ok(GetCollectionsResponse::NotFound)
         } else {
@@ -49,16 +92,23 @@ impl Api {
         }
     }
 
-    pub async fn get_thumbs(&self, collection_id: i64) -> Result<GetThumbsResponse> {
+    pub async fn get_thumbs(
+        &self,
+        session: Session,
+        collection_id: i64,
+        filter: ListFilter,
+    ) -> Result<GetThumbsResponse> {
         let collections = &self.state.config.collections;
         let coll = match collections.iter().find(|c| c.collection_id as i64 == collection_id) {
             Some(coll) => coll,
             None => return Ok(GetThumbsResponse::NotFound),
         };
-        let mut items = models::MediaInfoOverview::get(
+        let (mut items, total) = models::MediaInfoOverview::list(
             &self.state.db.handle,
             coll.collection_id as i64,
             coll.subtype(),
+            session.user_id,
+            &filter,
         )
         .await?;
         let m = items
@@ -69,6 +119,44 @@ impl Api {
                 poster: i.poster,
             })
             .collect::<Vec<_>>();
-        Ok(GetThumbsResponse::Ok(Json(m)))
+        Ok(GetThumbsResponse::Ok(Json(m), total))
+    }
+
+    pub async fn get_facets(
+        &self,
+        collection_id: i64,
+        facet: FacetType,
+    ) -> Result<GetFacetsResponse> {
+        let collections = &self.state.config.collections;
+        let coll = match collections.iter().find(|c| c.collection_id as i64 == collection_id) {
+            Some(coll) => coll,
+            None => return Ok(GetFacetsResponse::NotFound),
+        };
+        let facets =
+            Facet::list(&self.state.db.handle, coll.collection_id as i64, coll.subtype(), facet)
+                .await?;
+        Ok(GetFacetsResponse::Ok(Json(facets)))
+    }
+
+    pub async fn scan_collection(&self, collection_id: i64) -> Result<ScanCollectionResponse> {
+        let collection_id = match u32::try_from(collection_id) {
+            Ok(id) => id,
+            Err(_) => return Ok(ScanCollectionResponse::NotFound),
+        };
+        let scanner = &self.state.scanner;
+        let started = scanner.trigger(collection_id);
+        match scanner.status(collection_id) {
+            Some(status) if started => Ok(ScanCollectionResponse::Started(Json(status))),
+            Some(status) => Ok(ScanCollectionResponse::Running(Json(status))),
+            None => Ok(ScanCollectionResponse::NotFound),
+        }
+    }
+
+    pub async fn get_scan_status(&self, collection_id: i64) -> Result<GetScanStatusResponse> {
+        let status = u32::try_from(collection_id).ok().and_then(|id| self.state.scanner.status(id));
+        match status {
+            Some(status) => Ok(GetScanStatusResponse::Ok(Json(status))),
+            None => Ok(GetScanStatusResponse::NotFound),
+        }
     }
 }
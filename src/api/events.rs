@@ -0,0 +1,67 @@
+use std::time::Duration;
+
+use futures_util::stream::{BoxStream, StreamExt};
+use poem_openapi::payload::EventStream;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::Instant;
+
+use super::Api;
+use crate::db::Db;
+use crate::events::{Event, EventType};
+use crate::models::Session;
+
+// Send a comment every so often, so that proxies keep the connection open.
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+// Check this often that the session was not revoked or timed out.
+const CHECK_SESSION: Duration = Duration::from_secs(60);
+
+impl Api {
+    pub fn events(&self, session: Session) -> EventStream<BoxStream<'static, Event>> {
+        let mut rx = self.state.events.subscribe();
+        let db = self.state.db.clone();
+        let timeout = self.state.config.session.timeout;
+        let mut check = tokio::time::interval_at(Instant::now() + CHECK_SESSION, CHECK_SESSION);
+        let stream = async_stream::stream! {
+            loop {
+                let res = tokio::select! {
+                    res = rx.recv() => Some(res),
+                    _ = check.tick() => None,
+                };
+                match res {
+                    Some(Ok(event)) => {
+                        if event.is_for(&session) {
+                            yield event;
+                        }
+                    },
+                    Some(Err(RecvError::Lagged(n))) => {
+                        log::debug!("events: {}: lost {} events", session.username, n);
+                        yield Event::new(EventType::Resync);
+                    },
+                    Some(Err(RecvError::Closed)) => break,
+                    None => {
+                        if !is_valid(&db, &session, timeout).await {
+                            log::debug!("events: {}: session ended", session.username);
+                            break;
+                        }
+                    },
+                }
+            }
+        };
+        EventStream::new(stream.boxed()).keep_alive(KEEP_ALIVE)
+    }
+}
+
+async fn is_valid(db: &Db, session: &Session, timeout: Option<Duration>) -> bool {
+    let res = async {
+        let mut txn = db.handle.begin().await?;
+        Session::is_valid(&mut txn, session, timeout).await
+    };
+    match res.await {
+        Ok(valid) => valid,
+        Err(e) => {
+            // Don't drop the client because of a database hiccup.
+            log::error!("events: {}", e);
+            true
+        },
+    }
+}
@@ -1,23 +1,33 @@
+use std::time::SystemTime;
+
+use ::image::ImageFormat;
 use poem::{
-    error::NotFoundError, web::StaticFileRequest, Body, FromRequest, IntoResponse, Request,
-    ResponseParts, Result,
+    error::NotFoundError, http::header, web::StaticFileRequest, Body, FromRequest, IntoResponse,
+    Request, ResponseParts, Result,
 };
 use poem_openapi::payload::{Binary, Response};
 
 use super::Api;
-use crate::models;
-use crate::util::Id;
+use crate::models::{self, FileInfo};
+use crate::util::{Id, SystemTimeToUnixTime};
+
+// Default maximum size of the image cache, in MB.
+const DEFAULT_CACHE_SIZE: u64 = 1000;
 
 #[derive(serde::Deserialize, Debug)]
 pub struct ImageOpts {
     pub width: Option<u32>,
     pub height: Option<u32>,
     pub quality: Option<u32>,
+    pub format: Option<String>,
 }
 
 impl ImageOpts {
     fn is_some(&self) -> bool {
-        self.width.is_some() || self.height.is_some() || self.quality.is_some()
+        self.width.is_some()
+            || self.height.is_some()
+            || self.quality.is_some()
+            || self.format.is_some()
     }
 }
 
@@ -25,31 +35,274 @@ impl Api {
     /// Retrieve image.
     pub async fn get_image(
         &self,
-        collection_id: u32,
         mediaitem_id: Id,
         image_id: i64,
         whq: ImageOpts,
         req: &Request,
     ) -> Result<Response<Binary<Body>>> {
-        let coll = self.state.config.get_collection(collection_id).ok_or(NotFoundError)?;
         let mi = models::MediaInfo::get(&self.state.db.handle, mediaitem_id)
             .await?
             .ok_or(NotFoundError)?;
+        let coll = self.state.config.get_collection(mi.collection_id).ok_or(NotFoundError)?;
         let img = mi.thumbs.iter().find(|i| i.image_id == image_id).ok_or(NotFoundError)?;
-        let mut file = format!("{}/{}/{}", coll.directory, mi.directory.path, img.fileinfo.path);
+        let basedir = format!("{}/{}", coll.directory, mi.directory.path);
+        let mut file = FileInfo::join(&basedir, &img.fileinfo.path);
+
+        // Without an explicit format, the response depends on the Accept header.
+        let vary = whq.is_some() && whq.format.is_none();
 
         if whq.is_some() {
-            let outfile = "/tmp/test.jpg".to_string();
-            resize_image(&file, &outfile, whq).await?;
-            file = outfile;
+            // The original, as it is on the filesystem right now.
+            let fileinfo = FileInfo::from_path(&basedir, &img.fileinfo.path)
+                .await
+                .map_err(|_| NotFoundError)?;
+            let accept = req.headers().get(header::ACCEPT).and_then(|v| v.to_str().ok());
+            file = self.resized_image(&mi, img, fileinfo, whq, accept).await?;
         }
 
         // Create static file responder.
         let sfr = StaticFileRequest::from_request_without_body(req).await?;
         let poem_resp = sfr.create_response(&file, true)?.into_response();
 
-        Ok(poem_response_to_binary(poem_resp))
+        let mut resp = poem_response_to_binary(poem_resp);
+        if vary {
+            resp = resp.header(header::VARY, "Accept");
+        }
+        Ok(resp)
+    }
+
+    // Find the resized image in the cache, or create it.
+    //
+    // Returns the full path of the resized image.
+    async fn resized_image(
+        &self,
+        mi: &models::MediaInfo,
+        thumb: &models::Thumb,
+        fileinfo: FileInfo,
+        whq: ImageOpts,
+        accept: Option<&str>,
+    ) -> anyhow::Result<String> {
+        let cachedir = self.cachedir();
+        let now = SystemTime::now().unixtime_ms();
+
+        // Find the original in the database. If it has changed on
+        // the filesystem, the resized variants are out of date.
+        let mut txn = self.state.db.handle.begin().await?;
+        let orig = match models::Image::original(&mut txn, mi.id, &fileinfo.path).await? {
+            Some(orig) if orig.fileinfo == fileinfo => orig,
+            old => {
+                if let Some(old) = old {
+                    log::debug!("get_image: {} changed, removing variants", fileinfo.path);
+                    for variant in models::Image::variants(&mut txn, old.id).await? {
+                        let _ = tokio::fs::remove_file(FileInfo::join(
+                            &cachedir,
+                            &variant.fileinfo.path,
+                        ))
+                        .await;
+                    }
+                    old.delete(&mut txn).await?;
+                }
+                let (width, height, format, alpha) = image_info(&fileinfo.fullpath).await?;
+                let mut orig = models::Image {
+                    collection_id: mi.collection_id,
+                    mediaitem_id: mi.id,
+                    fileinfo: fileinfo.clone(),
+                    aspect: thumb.aspect.clone(),
+                    width,
+                    height,
+                    quality: 100,
+                    format: extension(format).to_string(),
+                    alpha,
+                    ..models::Image::default()
+                };
+                orig.insert(&mut txn).await?;
+                orig
+            },
+        };
+
+        let format = choose_format(whq.format.as_deref(), accept, orig.alpha);
+        let ext = extension(format);
+        let (w, h, q) = decode_whq(whq, orig.width, orig.height);
+        if (w, h, q, ext) == (orig.width, orig.height, 100, orig.format.as_str()) {
+            txn.commit().await?;
+            return Ok(fileinfo.fullpath);
+        }
+
+        // Do we have it in the cache? Check that the file is still there.
+        if let Some(mut variant) = models::Image::variant(&mut txn, orig.id, w, h, q, ext).await? {
+            if let Ok(current) = FileInfo::from_path(&cachedir, &variant.fileinfo.path).await {
+                if current == variant.fileinfo {
+                    // Don't update the timestamp on every request.
+                    if variant.accessed < now - 60_000 {
+                        variant.touch(&mut txn, now).await?;
+                    }
+                    txn.commit().await?;
+                    return Ok(current.fullpath);
+                }
+            }
+        }
+        txn.commit().await?;
+
+        // Resize into the cache. Write to a temporary file first, so that
+        // concurrent requests never see a half-written image.
+        let path = format!("{}/{}-{}x{}-q{}.{}", mi.id, orig.id, w, h, q, ext);
+        let fullpath = FileInfo::join(&cachedir, &path);
+        tokio::fs::create_dir_all(FileInfo::join(&cachedir, &mi.id.to_string())).await?;
+        let tmpfile = format!("{}.{}.tmp", fullpath, Id::new());
+        let whq = ImageOpts {
+            width: Some(w),
+            height: Some(h),
+            quality: Some(q),
+            format: None,
+        };
+        if let Err(e) = resize_image(&fileinfo.fullpath, &tmpfile, whq, format).await {
+            let _ = tokio::fs::remove_file(&tmpfile).await;
+            return Err(e);
+        }
+        tokio::fs::rename(&tmpfile, &fullpath).await?;
+
+        let mut variant = models::Image {
+            collection_id: mi.collection_id,
+            mediaitem_id: mi.id,
+            image_id: orig.id,
+            fileinfo: FileInfo::from_path(&cachedir, &path).await?,
+            aspect: orig.aspect.clone(),
+            width: w,
+            height: h,
+            quality: q,
+            accessed: now,
+            format: ext.to_string(),
+            alpha: orig.alpha && format != ImageFormat::Jpeg,
+            ..models::Image::default()
+        };
+        let mut txn = self.state.db.handle.begin().await?;
+        variant.insert(&mut txn).await?;
+        txn.commit().await?;
+
+        if let Err(e) = self.evict_images(&cachedir, variant.id).await {
+            log::error!("get_image: evicting images from the cache: {}", e);
+        }
+
+        Ok(fullpath)
+    }
+
+    // Remove the least recently used images until the cache is small enough.
+    // The image we're about to serve (`keep`) is never removed.
+    async fn evict_images(&self, cachedir: &str, keep: i64) -> anyhow::Result<()> {
+        let max_size =
+            self.state.config.server.cache_size.unwrap_or(DEFAULT_CACHE_SIZE) * 1_000_000;
+        let mut txn = self.state.db.handle.begin().await?;
+        let mut size = models::Image::cache_size(&mut txn).await?;
+        if size > max_size {
+            // Make some room, so that we don't have to do this on every request.
+            let target = max_size / 10 * 9;
+            for image in models::Image::least_recently_used(&mut txn).await? {
+                if size <= target {
+                    break;
+                }
+                if image.id == keep {
+                    continue;
+                }
+                log::trace!("get_image: evicting {}", image.fileinfo.path);
+                let _ =
+                    tokio::fs::remove_file(FileInfo::join(cachedir, &image.fileinfo.path)).await;
+                image.delete(&mut txn).await?;
+                size = size.saturating_sub(image.fileinfo.size);
+            }
+        }
+        txn.commit().await?;
+        Ok(())
     }
+
+    fn cachedir(&self) -> String {
+        match self.state.config.server.cachedir.as_ref() {
+            Some(dir) => dir.clone(),
+            None => FileInfo::join(&std::env::temp_dir().to_string_lossy(), "notflix-cache"),
+        }
+    }
+}
+
+// Read the dimensions and format of an image, and find out if it has an alpha channel.
+async fn image_info(file: &str) -> anyhow::Result<(u32, u32, ImageFormat, bool)> {
+    use ::image::codecs::{png::PngDecoder, webp::WebPDecoder};
+    use ::image::{io::Reader as ImageReader, ImageDecoder};
+
+    fn info<'a>(dec: impl ImageDecoder<'a>) -> (u32, u32, bool) {
+        let (w, h) = dec.dimensions();
+        (w, h, dec.color_type().has_alpha())
+    }
+
+    tokio::task::block_in_place(move || {
+        let rdr = std::io::BufReader::new(std::fs::File::open(file)?);
+        let rdr = ImageReader::new(rdr).with_guessed_format()?;
+        let format = match rdr.format() {
+            Some(format) => format,
+            None => bail!("{}: unknown image format", file),
+        };
+        let (w, h, alpha) = match format {
+            ImageFormat::Png => info(PngDecoder::new(rdr.into_inner())?),
+            ImageFormat::WebP => info(WebPDecoder::new(rdr.into_inner())?),
+            _ => {
+                let (w, h) = rdr.into_dimensions()?;
+                (w, h, false)
+            },
+        };
+        Ok((w, h, format, alpha))
+    })
+}
+
+// Pick the format of a resized image. An explicit `fmt=` wins if we can
+// encode it, otherwise it's the best format that the client accepts.
+// Images with an alpha channel are not flattened to JPEG.
+fn choose_format(fmt: Option<&str>, accept: Option<&str>, alpha: bool) -> ImageFormat {
+    if let Some(format) = fmt.and_then(ImageFormat::from_extension) {
+        if can_encode(format) {
+            return format;
+        }
+    }
+    let accepts = |mime: &str| accept.map(|a| accepts(a, mime)).unwrap_or(false);
+    if can_encode(ImageFormat::Avif) && accepts("image/avif") {
+        return ImageFormat::Avif;
+    }
+    // Lossless WebP is smaller than PNG, but not than JPEG.
+    if (LOSSY_WEBP || alpha) && accepts("image/webp") {
+        return ImageFormat::WebP;
+    }
+    if alpha {
+        ImageFormat::Png
+    } else {
+        ImageFormat::Jpeg
+    }
+}
+
+// Without libwebp, the image crate can only encode lossless WebP.
+const LOSSY_WEBP: bool = cfg!(any(feature = "with-webp", feature = "with-magick_rust"));
+
+fn can_encode(format: ImageFormat) -> bool {
+    match format {
+        ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::WebP => true,
+        ImageFormat::Avif => cfg!(any(feature = "with-avif", feature = "with-magick_rust")),
+        _ => false,
+    }
+}
+
+// Does the Accept header list this mime type (with a non-zero q)?
+// Wildcards don't count, clients that send `*/*` or `image/*` might
+// not be able to decode WebP or AVIF.
+fn accepts(accept: &str, mime: &str) -> bool {
+    accept.split(',').any(|range| {
+        let mut params = range.split(';').map(|p| p.trim());
+        let is_mime = params.next().map(|m| m.eq_ignore_ascii_case(mime)).unwrap_or(false);
+        is_mime
+            && params.all(|p| match p.strip_prefix("q=").map(|q| q.parse::<f32>()) {
+                Some(Ok(q)) => q > 0.0,
+                _ => true,
+            })
+    })
+}
+
+fn extension(format: ImageFormat) -> &'static str {
+    format.extensions_str().first().copied().unwrap_or("bin")
 }
 
 fn poem_response_to_binary(resp: poem::Response) -> Response<Binary<poem::Body>> {
@@ -103,18 +356,29 @@ mod resize {
     use std::io::{BufReader, BufWriter};
 
     use super::*;
-    use ::image::{codecs::jpeg::JpegEncoder, io::Reader as ImageReader};
+    #[cfg(feature = "with-avif")]
+    use ::image::codecs::avif::AvifEncoder;
+    #[cfg(feature = "with-webp")]
+    use ::image::codecs::webp::WebPQuality;
+    use ::image::codecs::{jpeg::JpegEncoder, png::PngEncoder, webp::WebPEncoder};
+    use ::image::{io::Reader as ImageReader, DynamicImage, ImageEncoder};
     use tokio::task;
 
     pub(super) async fn resize_image(
         infile: &str,
         outfile: &str,
         whq: ImageOpts,
+        format: ImageFormat,
     ) -> anyhow::Result<()> {
-        task::block_in_place(move || resize(infile, outfile, whq))
+        task::block_in_place(move || resize(infile, outfile, whq, format))
     }
 
-    fn resize(infile: &str, outfile: &str, whq: ImageOpts) -> anyhow::Result<()> {
+    fn resize(
+        infile: &str,
+        outfile: &str,
+        whq: ImageOpts,
+        format: ImageFormat,
+    ) -> anyhow::Result<()> {
         let begin = std::time::Instant::now();
 
         let mut img = {
@@ -154,11 +418,37 @@ mod resize {
         log::trace!("get_image: time_to_resize: {:?}", begin.elapsed());
 
         let begin = std::time::Instant::now();
+        // JPEG has no alpha channel, and the other encoders want 8 bits per channel.
+        let img = match format {
+            ImageFormat::Jpeg => DynamicImage::ImageRgb8(img.into_rgb8()),
+            _ if img.color().has_alpha() => DynamicImage::ImageRgba8(img.into_rgba8()),
+            _ => DynamicImage::ImageRgb8(img.into_rgb8()),
+        };
+        let (data, width, height, color) = (img.as_bytes(), img.width(), img.height(), img.color());
+
         let output = StdFile::create(&outfile)?;
         let output = BufWriter::with_capacity(32768, output);
-        let mut jpeg = JpegEncoder::new_with_quality(output, q as u8);
-        jpeg.encode(img.as_bytes(), img.width(), img.height(), img.color())?;
-        log::trace!("get_image:: time_to_encode: {:?}", begin.elapsed());
+        match format {
+            ImageFormat::Png => PngEncoder::new(output).write_image(data, width, height, color)?,
+            ImageFormat::WebP => {
+                #[cfg(feature = "with-webp")]
+                let webp = WebPEncoder::new_with_quality(output, WebPQuality::lossy(q as u8));
+                #[cfg(not(feature = "with-webp"))]
+                let webp = WebPEncoder::new_lossless(output);
+                webp.encode(data, width, height, color)?;
+            },
+            #[cfg(feature = "with-avif")]
+            ImageFormat::Avif => {
+                // Speed 1 (slowest) - 10 (fastest).
+                let avif = AvifEncoder::new_with_speed_quality(output, 8, q as u8);
+                avif.write_image(data, width, height, color)?;
+            },
+            _ => {
+                let mut jpeg = JpegEncoder::new_with_quality(output, q as u8);
+                jpeg.encode(data, width, height, color)?;
+            },
+        }
+        log::trace!("get_image:: time_to_encode {}: {:?}", extension(format), begin.elapsed());
 
         Ok(())
     }
@@ -245,14 +535,20 @@ mod magick_resize {
         infile: &str,
         outfile: &str,
         whq: ImageOpts,
+        format: ImageFormat,
     ) -> anyhow::Result<()> {
         tokio::task::block_in_place(move || {
-            sync_resize(infile, outfile, whq)?;
+            sync_resize(infile, outfile, whq, format)?;
             Ok::<_, anyhow::Error>(())
         })
     }
 
-    fn sync_resize(infile: &str, outfile: &str, whq: ImageOpts) -> anyhow::Result<()> {
+    fn sync_resize(
+        infile: &str,
+        outfile: &str,
+        whq: ImageOpts,
+        format: ImageFormat,
+    ) -> anyhow::Result<()> {
         START.call_once(|| {
             magick_wand_genesis();
         });
@@ -263,6 +559,8 @@ mod magick_resize {
         let src_height = wand.get_image_height() as u32;
         let (dst_width, dst_height, q) = decode_whq(whq, src_width, src_height);
         wand.thumbnail_image(dst_width as usize, dst_height as usize);
+        // The output file has a temporary name, so set the format explicitly.
+        wand.set_image_format(&extension(format).to_uppercase())?;
         if q != 100 {
             let _ = wand.set_image_compression_quality(q as usize);
             let _ = wand.strip_image();
@@ -275,3 +573,44 @@ mod magick_resize {
 }
 #[cfg(feature = "with-magick_rust")]
 use magick_resize::resize_image;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accepts() {
+        assert!(accepts("image/webp,*/*", "image/webp"));
+        assert!(accepts("IMAGE/WebP;q=0.5", "image/webp"));
+        assert!(!accepts("image/webp;q=0", "image/webp"));
+        assert!(!accepts("image/png, image/webp; q=0.0", "image/webp"));
+        assert!(!accepts("image/*,*/*;q=0.8", "image/webp"));
+    }
+
+    #[test]
+    fn test_choose_format() {
+        let browser = Some("image/avif,image/webp,image/apng,image/*,*/*;q=0.8");
+
+        // An explicit format wins, if we can encode it.
+        assert_eq!(choose_format(Some("png"), browser, false), ImageFormat::Png);
+        assert_ne!(choose_format(Some("gif"), None, false), ImageFormat::Gif);
+
+        // Wildcards and q=0 don't select WebP or AVIF.
+        assert_eq!(choose_format(None, Some("*/*"), false), ImageFormat::Jpeg);
+        assert_eq!(choose_format(None, Some("image/*"), true), ImageFormat::Png);
+        assert_eq!(choose_format(None, Some("image/webp;q=0"), true), ImageFormat::Png);
+        assert_eq!(choose_format(None, Some("image/avif;q=0"), false), ImageFormat::Jpeg);
+
+        // Alpha is not flattened to JPEG, unless that was asked for.
+        assert_eq!(choose_format(None, None, true), ImageFormat::Png);
+        assert_eq!(choose_format(None, Some("image/webp"), true), ImageFormat::WebP);
+        assert_eq!(choose_format(Some("jpg"), None, true), ImageFormat::Jpeg);
+
+        let expected = match (can_encode(ImageFormat::Avif), LOSSY_WEBP) {
+            (true, _) => ImageFormat::Avif,
+            (false, true) => ImageFormat::WebP,
+            (false, false) => ImageFormat::Jpeg,
+        };
+        assert_eq!(choose_format(None, browser, false), expected);
+    }
+}
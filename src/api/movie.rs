@@ -1,18 +1,77 @@
 use super::Api;
 use crate::db::FindItemBy;
 use anyhow::Result;
-use poem_openapi::{payload::Json, ApiResponse};
+use poem_openapi::{payload::Json, ApiResponse, Object};
 
-pub use crate::models::Movie;
+use crate::jvec::JVec;
+use crate::kodifs;
+use crate::models::{self, is_default, FileInfo, Nfo, Subtitle, Thumb, Video};
 use crate::util::Id;
 
+/// Movie details.
+#[derive(Object)]
+pub struct Movie {
+    /// Unique ID
+    #[oai(read_only)]
+    pub id: Id,
+    /// Collection id
+    pub collection_id: u32,
+    /// Title
+    pub title: String,
+    /// Year
+    #[oai(skip_serializing_if = "is_default")]
+    pub year: Option<u32>,
+    /// Date added YYYY-MM-DD
+    pub dateadded: String,
+    /// Images. Thumbnails and fanart.
+    #[oai(skip_serializing_if = "is_default")]
+    pub thumbs: JVec<Thumb>,
+    /// Info from the NFO file.
+    #[oai(flatten)]
+    pub nfo: Nfo,
+    /// Information about the video.
+    pub video: Option<Video>,
+    /// External subtitles.
+    #[oai(skip_serializing_if = "is_default")]
+    pub subtitles: JVec<Subtitle>,
+}
+
+impl From<models::MediaItem> for Movie {
+    fn from(item: models::MediaItem) -> Movie {
+        let video = video_info(item.directory.as_ref(), item.video_file.as_ref(), item.video_info);
+        Movie {
+            id: item.id,
+            collection_id: item.collection_id,
+            title: item.title,
+            year: item.year,
+            dateadded: item.dateadded,
+            thumbs: item.thumbs,
+            nfo: item.nfo_info.unwrap_or_default(),
+            video,
+            subtitles: item.subtitles,
+        }
+    }
+}
+
+// Fill in the path of the video, relative to the collection directory.
+pub(super) fn video_info(
+    dir: Option<&FileInfo>,
+    file: Option<&FileInfo>,
+    info: Option<Video>,
+) -> Option<Video> {
+    let file = file?;
+    let mut video = info.unwrap_or_default();
+    video.path = kodifs::join_and_escape_path(dir.map(|d| d.path.as_str()), &file.path);
+    Some(video)
+}
+
 #[derive(ApiResponse)]
 pub enum GetMovieResponse {
-    /// Return when the collections are listed.
+    /// Return when the movie was found.
     #[oai(status = 200)]
-    Ok(Json<Box<Movie>>),
+    Ok(Json<Movie>),
 
-    /// Return when there are no collections.
+    /// Return when the movie was not found.
     #[oai(status = 404)]
     NotFound,
 }
@@ -26,8 +85,11 @@ impl Api {
         };
         let mut txn = self.state.db.handle.begin().await?;
         let by = FindItemBy::id(movie_id, false);
-        match Movie::lookup_by(&mut txn, &by).await? {
-            Some(movie) => Ok(GetMovieResponse::Ok(Json(movie))),
+        let item = models::MediaItem::lookup_by(&mut txn, &by)
+            .await?
+            .filter(|m| m.type_ == "movie" && m.collection_id as i64 == collection_id);
+        match item {
+            Some(movie) => Ok(GetMovieResponse::Ok(Json(Movie::from(*movie)))),
             None => Ok(GetMovieResponse::NotFound),
         }
     }
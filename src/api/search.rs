@@ -0,0 +1,24 @@
+use anyhow::Result;
+use poem_openapi::{payload::Json, ApiResponse};
+
+use super::Api;
+use crate::models::SearchResult;
+
+// Maximum number of search results.
+const MAX_LIMIT: u32 = 200;
+
+#[derive(ApiResponse)]
+pub enum SearchResponse {
+    /// Movies, tvshows and episodes, best matches first.
+    #[oai(status = 200)]
+    Ok(Json<Vec<SearchResult>>),
+}
+
+impl Api {
+    pub async fn search(&self, query: &str, limit: Option<u32>) -> Result<SearchResponse> {
+        let mut txn = self.state.db.handle.begin().await?;
+        let limit = limit.unwrap_or(50).min(MAX_LIMIT);
+        let items = SearchResult::search(&mut txn, query, limit).await?;
+        Ok(SearchResponse::Ok(Json(items)))
+    }
+}
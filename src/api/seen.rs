@@ -0,0 +1,165 @@
+use anyhow::Result;
+use poem_openapi::{payload::Json, ApiResponse, Object};
+
+use super::Api;
+use crate::db::FindItemBy;
+use crate::events::Event;
+use crate::models::{MediaItem, Seen, SeenItem, Session};
+use crate::util::Id;
+
+/// Update watch progress schema
+#[derive(Debug, Object, Clone)]
+pub struct UpdateProgress {
+    /// Playback position in seconds.
+    pub position: f64,
+    /// Duration in seconds.
+    pub duration: Option<f64>,
+    /// Watched until the end. If not set, this is true when
+    /// more than 90% of `duration` has been watched.
+    pub completed: Option<bool>,
+}
+
+/// Mark tvshow as seen schema
+#[derive(Debug, Object, Clone)]
+pub struct MarkSeen {
+    /// Only this season.
+    pub season: Option<u32>,
+    /// Seen or unseen.
+    pub seen: bool,
+}
+
+#[derive(ApiResponse)]
+pub enum GetProgressResponse {
+    /// Watch progress.
+    #[oai(status = 200)]
+    Ok(Json<Seen>),
+    /// Item not found, or never watched.
+    #[oai(status = 404)]
+    NotFound,
+}
+
+#[derive(ApiResponse)]
+pub enum UpdateProgressResponse {
+    /// Watch progress updated.
+    #[oai(status = 200)]
+    Ok(Json<Seen>),
+    /// Movie or episode not found.
+    #[oai(status = 404)]
+    NotFound,
+}
+
+#[derive(ApiResponse)]
+pub enum MarkSeenResponse {
+    /// Number of episodes updated.
+    #[oai(status = 200)]
+    Ok(Json<u64>),
+    /// TV show not found.
+    #[oai(status = 404)]
+    NotFound,
+}
+
+#[derive(ApiResponse)]
+pub enum GetSeenResponse {
+    /// Watch progress, most recent first.
+    #[oai(status = 200)]
+    Ok(Json<Vec<Seen>>),
+}
+
+#[derive(ApiResponse)]
+pub enum GetSeenItemsResponse {
+    /// List of movies and episodes.
+    #[oai(status = 200)]
+    Ok(Json<Vec<SeenItem>>),
+}
+
+impl Api {
+    pub async fn get_progress(
+        &self,
+        session: Session,
+        mediaitem_id: Id,
+    ) -> Result<GetProgressResponse> {
+        let mut txn = self.state.db.handle.begin().await?;
+        match Seen::get(&mut txn, session.user_id, mediaitem_id).await? {
+            Some(seen) => Ok(GetProgressResponse::Ok(Json(seen))),
+            None => Ok(GetProgressResponse::NotFound),
+        }
+    }
+
+    pub async fn update_progress(
+        &self,
+        session: Session,
+        mediaitem_id: Id,
+        progress: UpdateProgress,
+    ) -> Result<UpdateProgressResponse> {
+        let mut txn = self.state.db.handle.begin().await?;
+
+        // Only movies and episodes have a video.
+        let by = FindItemBy::id(mediaitem_id, false);
+        let item = match MediaItem::lookup_by(&mut txn, &by).await? {
+            Some(item) if item.type_ == "movie" || item.type_ == "episode" => item,
+            _ => return Ok(UpdateProgressResponse::NotFound),
+        };
+
+        let completed = progress.completed.unwrap_or_else(|| match progress.duration {
+            Some(d) if d > 0f64 => progress.position / d > 0.9,
+            _ => false,
+        });
+        let mut seen = Seen {
+            mediaitem_id,
+            position: progress.position,
+            duration: progress.duration,
+            completed,
+            updated: 0,
+        };
+        seen.set(&mut txn, session.user_id).await?;
+        txn.commit().await?;
+        self.state.events.send(Event::seen(&session, mediaitem_id, &item.type_));
+
+        Ok(UpdateProgressResponse::Ok(Json(seen)))
+    }
+
+    pub async fn mark_tvshow_seen(
+        &self,
+        session: Session,
+        tvshow_id: Id,
+        mark: MarkSeen,
+    ) -> Result<MarkSeenResponse> {
+        let mut txn = self.state.db.handle.begin().await?;
+
+        let by = FindItemBy::id(tvshow_id, false);
+        match MediaItem::lookup_by(&mut txn, &by).await? {
+            Some(item) if item.type_ == "tvshow" => {},
+            _ => return Ok(MarkSeenResponse::NotFound),
+        }
+
+        let n =
+            Seen::mark_tvshow(&mut txn, session.user_id, tvshow_id, mark.season, mark.seen).await?;
+        txn.commit().await?;
+        self.state.events.send(Event::seen(&session, tvshow_id, "tvshow"));
+
+        Ok(MarkSeenResponse::Ok(Json(n)))
+    }
+
+    pub async fn get_seen(&self, session: Session, since: Option<i64>) -> Result<GetSeenResponse> {
+        let mut txn = self.state.db.handle.begin().await?;
+        let seen = Seen::since(&mut txn, session.user_id, since.unwrap_or(0)).await?;
+        Ok(GetSeenResponse::Ok(Json(seen)))
+    }
+
+    pub async fn get_continue_watching(
+        &self,
+        session: Session,
+        limit: Option<u32>,
+    ) -> Result<GetSeenItemsResponse> {
+        let mut txn = self.state.db.handle.begin().await?;
+        let limit = limit.unwrap_or(50);
+        let items = Seen::continue_watching(&mut txn, session.user_id, limit).await?;
+        Ok(GetSeenItemsResponse::Ok(Json(items)))
+    }
+
+    pub async fn get_next_up(&self, session: Session) -> Result<GetSeenItemsResponse> {
+        let mut txn = self.state.db.handle.begin().await?;
+        let items = Seen::next_up(&mut txn, session.user_id).await?;
+        Ok(GetSeenItemsResponse::Ok(Json(items)))
+    }
+}
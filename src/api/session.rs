@@ -0,0 +1,91 @@
+use anyhow::Result;
+use poem_openapi::{payload::Json, ApiResponse};
+
+use super::Api;
+use crate::models::{Session, SessionInfo};
+
+#[derive(ApiResponse)]
+pub enum GetSessionsResponse {
+    /// Active sessions, most recently used first.
+    #[oai(status = 200)]
+    Ok(Json<Vec<SessionInfo>>),
+
+    /// Not an admin, and not your own account.
+    #[oai(status = 403)]
+    Forbidden,
+}
+
+#[derive(ApiResponse)]
+pub enum RevokeSessionResponse {
+    /// Session revoked.
+    #[oai(status = 200)]
+    Ok,
+
+    /// Not an admin, and not your own account.
+    #[oai(status = 403)]
+    Forbidden,
+
+    /// Session not found.
+    #[oai(status = 404)]
+    NotFound,
+}
+
+#[derive(ApiResponse)]
+pub enum RevokeSessionsResponse {
+    /// Number of sessions revoked.
+    #[oai(status = 200)]
+    Ok(Json<u64>),
+
+    /// Not an admin, and not your own account.
+    #[oai(status = 403)]
+    Forbidden,
+}
+
+impl Api {
+    pub async fn get_sessions(
+        &self,
+        session: Session,
+        user_id: i64,
+    ) -> Result<GetSessionsResponse> {
+        if !session.admin && session.user_id != user_id {
+            return Ok(GetSessionsResponse::Forbidden);
+        }
+        let timeout = self.state.config.session.timeout;
+        let mut txn = self.state.db.handle.begin().await?;
+        let sessions = Session::list(&mut txn, user_id, &session.sessionid, timeout).await?;
+        Ok(GetSessionsResponse::Ok(Json(sessions)))
+    }
+
+    pub async fn revoke_session(
+        &self,
+        session: Session,
+        user_id: i64,
+        id: i64,
+    ) -> Result<RevokeSessionResponse> {
+        if !session.admin && session.user_id != user_id {
+            return Ok(RevokeSessionResponse::Forbidden);
+        }
+        let mut txn = self.state.db.handle.begin().await?;
+        let resp = match Session::delete_by_id(&mut txn, user_id, id).await? {
+            true => RevokeSessionResponse::Ok,
+            false => RevokeSessionResponse::NotFound,
+        };
+        txn.commit().await?;
+        Ok(resp)
+    }
+
+    /// Revoke all sessions of the user, except the current one.
+    pub async fn revoke_sessions(
+        &self,
+        session: Session,
+        user_id: i64,
+    ) -> Result<RevokeSessionsResponse> {
+        if !session.admin && session.user_id != user_id {
+            return Ok(RevokeSessionsResponse::Forbidden);
+        }
+        let mut txn = self.state.db.handle.begin().await?;
+        let count = Session::delete_others(&mut txn, user_id, &session.sessionid).await?;
+        txn.commit().await?;
+        Ok(RevokeSessionsResponse::Ok(Json(count)))
+    }
+}
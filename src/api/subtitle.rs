@@ -0,0 +1,84 @@
+use poem::{error::NotFoundError, http::header, Result};
+use poem_openapi::payload::{Binary, Response};
+
+use super::Api;
+use crate::models;
+use crate::util::Id;
+
+impl Api {
+    /// Retrieve subtitle file, converted to WebVTT if needed.
+    pub async fn get_subtitle(
+        &self,
+        mediaitem_id: Id,
+        subtitle_id: u32,
+    ) -> Result<Response<Binary<Vec<u8>>>> {
+        let mi = models::MediaInfo::get(&self.state.db.handle, mediaitem_id)
+            .await?
+            .ok_or(NotFoundError)?;
+        let coll = self.state.config.get_collection(mi.collection_id).ok_or(NotFoundError)?;
+        let sub = mi.subtitles.iter().find(|s| s.id == subtitle_id).ok_or(NotFoundError)?;
+        let file = format!("{}/{}/{}", coll.directory, mi.directory.path, sub.fileinfo.path);
+
+        let data = tokio::fs::read(&file).await.map_err(|_| NotFoundError)?;
+        let text = decode(&data);
+        let vtt = if file.ends_with(".srt") { srt_to_vtt(&text) } else { text };
+
+        let resp = Response::new(Binary(vtt.into_bytes()))
+            .header(header::CONTENT_TYPE, "text/vtt; charset=utf-8");
+        Ok(resp)
+    }
+}
+
+// Subtitle files are often not UTF-8 but in some legacy encoding,
+// like windows-1252. If there's no BOM and it's not valid UTF-8, guess.
+fn decode(data: &[u8]) -> String {
+    let encoding = match encoding_rs::Encoding::for_bom(data) {
+        Some((encoding, _)) => encoding,
+        None if std::str::from_utf8(data).is_ok() => encoding_rs::UTF_8,
+        None => {
+            let mut detector = chardetng::EncodingDetector::new();
+            detector.feed(data, true);
+            detector.guess(None, true)
+        },
+    };
+    let (text, _, _) = encoding.decode(data);
+    text.into_owned()
+}
+
+// SRT and WebVTT are close enough. Add the header, and in the
+// timestamps, replace the decimal comma with a dot.
+fn srt_to_vtt(srt: &str) -> String {
+    let mut vtt = String::with_capacity(srt.len() + 8);
+    vtt.push_str("WEBVTT\n\n");
+    for line in srt.lines() {
+        if line.contains("-->") {
+            vtt.push_str(&line.replace(',', "."));
+        } else {
+            vtt.push_str(line);
+        }
+        vtt.push('\n');
+    }
+    vtt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_srt_to_vtt() {
+        let srt = "1\r\n00:00:01,000 --> 00:00:02,500\r\nHello, world\r\n\r\n";
+        let vtt = "WEBVTT\n\n1\n00:00:01.000 --> 00:00:02.500\nHello, world\n\n";
+        assert_eq!(srt_to_vtt(srt), vtt);
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode(b"\xef\xbb\xbfcaf\xc3\xa9"), "café");
+        assert_eq!(decode(b"caf\xc3\xa9"), "café");
+        assert_eq!(
+            decode(b"Ce caf\xe9 est tr\xe8s bon, tr\xe8s tr\xe8s bon."),
+            "Ce café est très bon, très très bon."
+        );
+    }
+}
@@ -0,0 +1,58 @@
+use anyhow::Result;
+use poem_openapi::{payload::Json, ApiResponse, Object};
+
+use super::Api;
+use crate::models::SyncItem;
+
+// Maximum number of items in one response.
+const MAX_LIMIT: u32 = 1000;
+
+#[derive(Object)]
+pub struct Changes {
+    /// Changed items, oldest change first.
+    pub items: Vec<SyncItem>,
+    /// Token to pass on the next sync.
+    pub token: String,
+    /// There are more changes, sync again right away.
+    pub more: bool,
+}
+
+#[derive(ApiResponse)]
+pub enum SyncResponse {
+    /// Items that were added, updated or deleted.
+    #[oai(status = 200)]
+    Ok(Json<Changes>),
+
+    /// The sync token is not valid.
+    #[oai(status = 400)]
+    BadToken,
+}
+
+impl Api {
+    pub async fn sync(
+        &self,
+        token: Option<&str>,
+        since: Option<i64>,
+        collection_id: Option<i64>,
+        limit: Option<u32>,
+    ) -> Result<SyncResponse> {
+        let since = match token {
+            Some(token) => match i64::from_str_radix(token, 16) {
+                Ok(stamp) if stamp >= 0 => stamp,
+                _ => return Ok(SyncResponse::BadToken),
+            },
+            None => since.unwrap_or(0).max(0),
+        };
+        let limit = limit.unwrap_or(500).clamp(1, MAX_LIMIT);
+
+        // Get one extra item to see if there are more.
+        let mut txn = self.state.db.handle.begin().await?;
+        let mut items = SyncItem::changes(&mut txn, since, collection_id, limit + 1).await?;
+        let more = items.len() > limit as usize;
+        items.truncate(limit as usize);
+
+        let stamp = items.last().map(|i| i.changed).unwrap_or(since);
+        let token = format!("{:x}", stamp);
+        Ok(SyncResponse::Ok(Json(Changes { items, token, more })))
+    }
+}
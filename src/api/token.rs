@@ -0,0 +1,107 @@
+use std::net::IpAddr;
+
+use anyhow::Result;
+use poem_openapi::{payload::Json, ApiResponse, Object};
+
+use super::Api;
+use crate::db::FindItemBy;
+use crate::kodifs::join_and_escape_path;
+use crate::media::safe_path;
+use crate::models::{MediaItem, Session};
+use crate::util::Id;
+
+/// Token for devices that can't send the session cookie.
+#[derive(Object)]
+pub struct MediaToken {
+    /// Add as `?token=` to the `/media` URL of the path, or of a file below it.
+    pub token: String,
+    /// When the token expires (unix timestamp in ms).
+    pub expires: i64,
+}
+
+#[derive(ApiResponse)]
+pub enum MediaTokenResponse {
+    /// A new token.
+    #[oai(status = 200)]
+    Ok(Json<MediaToken>),
+
+    /// The path is not valid.
+    #[oai(status = 400)]
+    BadPath,
+
+    /// Return when the collection was not found.
+    #[oai(status = 404)]
+    NotFound,
+}
+
+/// Signed URL to play a movie or episode.
+#[derive(Object)]
+pub struct PlaybackUrl {
+    /// URL of the video, relative to the server. Works without a session.
+    pub url: String,
+    /// When the URL expires (unix timestamp in ms).
+    pub expires: i64,
+}
+
+#[derive(ApiResponse)]
+pub enum PlaybackUrlResponse {
+    /// A new signed URL.
+    #[oai(status = 200)]
+    Ok(Json<PlaybackUrl>),
+
+    /// Movie or episode not found, or it has no video.
+    #[oai(status = 404)]
+    NotFound,
+}
+
+impl Api {
+    pub async fn media_token(
+        &self,
+        session: Session,
+        collection_id: u32,
+        path: &str,
+    ) -> Result<MediaTokenResponse> {
+        if self.state.config.get_collection(collection_id).is_none() {
+            return Ok(MediaTokenResponse::NotFound);
+        }
+        if !safe_path(path) {
+            return Ok(MediaTokenResponse::BadPath);
+        }
+        let (token, expires) = self.state.signer.sign(session.user_id, collection_id, path, None);
+        Ok(MediaTokenResponse::Ok(Json(MediaToken { token, expires })))
+    }
+
+    pub async fn playback_url(
+        &self,
+        session: Session,
+        mediaitem_id: Id,
+        ip: Option<IpAddr>,
+    ) -> Result<PlaybackUrlResponse> {
+        let mut txn = self.state.db.handle.begin().await?;
+        let by = FindItemBy::id(mediaitem_id, false);
+        let item = match MediaItem::lookup_by(&mut txn, &by).await? {
+            Some(item) if item.video_file.is_some() => item,
+            _ => return Ok(PlaybackUrlResponse::NotFound),
+        };
+
+        // Episodes are in the directory of the tvshow.
+        let dir = match item.tvshow_id {
+            Some(tvshow_id) => {
+                let by = FindItemBy::id(tvshow_id, false);
+                MediaItem::lookup_by(&mut txn, &by).await?.and_then(|t| t.directory)
+            },
+            None => item.directory,
+        };
+        let file = item.video_file.unwrap();
+        let path = match dir.as_ref() {
+            Some(dir) => format!("{}/{}", dir.path, file.path),
+            None => file.path.clone(),
+        };
+
+        let coll_id = item.collection_id;
+        let (token, expires) = self.state.signer.sign(session.user_id, coll_id, &path, ip);
+        let path = join_and_escape_path(dir.as_ref().map(|d| d.path.as_str()), &file.path);
+        let url = format!("/media/{}/{}?token={}", coll_id, path, token);
+        Ok(PlaybackUrlResponse::Ok(Json(PlaybackUrl { url, expires })))
+    }
+}
@@ -1,18 +1,95 @@
+use super::movie::video_info;
 use super::Api;
 use crate::db::FindItemBy;
 use crate::util::Id;
 use anyhow::Result;
-use poem_openapi::{payload::Json, ApiResponse};
+use poem_openapi::{payload::Json, ApiResponse, Object};
 
-pub use crate::models::TVShow;
+use crate::jvec::JVec;
+use crate::models::{self, is_default, FileInfo, Nfo, Subtitle, Thumb, Video};
+
+/// TV Show details.
+#[derive(Object)]
+pub struct TVShow {
+    /// Unique ID
+    #[oai(read_only)]
+    pub id: Id,
+    /// Collection id
+    pub collection_id: u32,
+    /// Title
+    pub title: String,
+    /// Date added YYYY-MM-DD
+    pub dateadded: String,
+    /// Images. Posters, fanart, season images.
+    #[oai(skip_serializing_if = "is_default")]
+    pub thumbs: JVec<Thumb>,
+    /// Info from the NFO file.
+    #[oai(flatten)]
+    pub nfo: Nfo,
+    /// Seasons and their episodes.
+    pub seasons: Vec<Season>,
+}
+
+/// One season.
+#[derive(Object)]
+pub struct Season {
+    pub season: u32,
+    pub episodes: Vec<Episode>,
+}
+
+/// One episode.
+#[derive(Object)]
+pub struct Episode {
+    /// Unique ID
+    #[oai(read_only)]
+    pub id: Id,
+    /// Title
+    pub title: String,
+    pub season: u32,
+    pub episode: u32,
+    /// Last episode, if this video contains more than one (e.g. 5 for S01E04E05).
+    #[oai(skip_serializing_if = "is_default")]
+    pub episode_last: Option<u32>,
+    /// Date added YYYY-MM-DD
+    pub dateadded: String,
+    /// Thumbnails.
+    #[oai(skip_serializing_if = "is_default")]
+    pub thumbs: JVec<Thumb>,
+    /// Info from the NFO file.
+    #[oai(flatten)]
+    pub nfo: Nfo,
+    /// Information about the video.
+    pub video: Option<Video>,
+    /// External subtitles.
+    #[oai(skip_serializing_if = "is_default")]
+    pub subtitles: JVec<Subtitle>,
+}
+
+impl Episode {
+    fn from_mediaitem(item: models::MediaItem, showdir: Option<&FileInfo>) -> Episode {
+        let video = video_info(showdir, item.video_file.as_ref(), item.video_info);
+        Episode {
+            id: item.id,
+            title: item.title,
+            season: item.season.unwrap_or(0),
+            episode: item.episode.unwrap_or(0),
+            episode_last: item.episode_last,
+            dateadded: item.dateadded,
+            thumbs: item.thumbs,
+            nfo: item.nfo_info.unwrap_or_default(),
+            video,
+            subtitles: item.subtitles,
+        }
+    }
+}
 
 #[derive(ApiResponse)]
 pub enum GetTVShowResponse {
-    /// Return when the collections are listed.
+    /// Return when the tvshow was found.
     #[oai(status = 200)]
-    Ok(Json<Box<TVShow>>),
+    Ok(Json<TVShow>),
 
-    /// Return when there are no collections.
+    /// Return when the tvshow was not found.
     #[oai(status = 404)]
     NotFound,
 }
@@ -26,9 +103,41 @@ impl Api {
         };
         let mut txn = self.state.db.handle.begin().await?;
         let by = FindItemBy::id(tvshow_id, false);
-        match TVShow::lookup_by(&mut txn, &by, true).await? {
-            Some(tvshow) => Ok(GetTVShowResponse::Ok(Json(tvshow))),
-            None => Ok(GetTVShowResponse::NotFound),
+        let item = models::MediaItem::lookup_by(&mut txn, &by)
+            .await?
+            .filter(|m| m.type_ == "tvshow" && m.collection_id as i64 == collection_id);
+        let item = match item {
+            Some(item) => item,
+            None => return Ok(GetTVShowResponse::NotFound),
+        };
+
+        // Group the episodes by season. They are already sorted.
+        let mut seasons: Vec<Season> = Vec::new();
+        for ep in models::MediaItem::get_episodes(&mut txn, item.id, false).await? {
+            let ep = Episode::from_mediaitem(ep, item.directory.as_ref());
+            match seasons.last_mut() {
+                Some(season) if season.season == ep.season => {
+                    // Skip episodes that are part of a multi-episode video.
+                    let prev = season.episodes.last().unwrap();
+                    if ep.episode <= prev.episode_last.unwrap_or(prev.episode) {
+                        continue;
+                    }
+                    season.episodes.push(ep);
+                },
+                _ => seasons.push(Season { season: ep.season, episodes: vec![ep] }),
+            }
         }
+
+        let item = *item;
+        let tvshow = TVShow {
+            id: item.id,
+            collection_id: item.collection_id,
+            title: item.title,
+            dateadded: item.dateadded,
+            thumbs: item.thumbs,
+            nfo: item.nfo_info.unwrap_or_default(),
+            seasons,
+        };
+        Ok(GetTVShowResponse::Ok(Json(tvshow)))
     }
 }
@@ -10,8 +10,8 @@ use poem_openapi::{
 };
 
 use super::{Api, Authenticate};
-use crate::models::{self, Session};
-use crate::util::some_or_return;
+use crate::models::{self, AuditEntry, AuditEvent, Session};
+use crate::ratelimit;
 
 /// Create user schema
 #[derive(Debug, Object, Clone, Eq, PartialEq)]
@@ -24,6 +24,8 @@ pub struct CreateUser {
     pub password: Password,
     /// Email address
     pub email: Option<Email>,
+    /// Administrator
+    pub admin: Option<bool>,
 }
 
 #[derive(ApiResponse)]
@@ -32,6 +34,10 @@ pub enum CreateUserResponse {
     #[oai(status = 200)]
     Ok(Json<i64>),
 
+    /// Only admins can create users.
+    #[oai(status = 403)]
+    Forbidden,
+
     /// User already exists.
     #[oai(status = 409)]
     Conflict,
@@ -45,6 +51,8 @@ pub struct UpdateUser {
     pub password: Option<Password>,
     /// Email address
     pub email: Option<Email>,
+    /// Administrator. Can only be changed by admins.
+    pub admin: Option<bool>,
 }
 
 #[derive(ApiResponse)]
@@ -52,6 +60,9 @@ pub enum UpdateUserResponse {
     /// User successfully updated.
     #[oai(status = 200)]
     Ok,
+    /// Not an admin, and not your own account.
+    #[oai(status = 403)]
+    Forbidden,
     /// User not found.
     #[oai(status = 404)]
     NotFound,
@@ -66,6 +77,19 @@ pub struct User {
     pub username: String,
     /// Email address
     pub email: Option<Email>,
+    /// Administrator
+    pub admin: bool,
+}
+
+impl From<models::User> for User {
+    fn from(user: models::User) -> User {
+        User {
+            id: user.id,
+            username: user.username,
+            email: user.email.map(Email),
+            admin: user.admin,
+        }
+    }
 }
 
 #[derive(ApiResponse, Debug)]
@@ -73,6 +97,9 @@ pub enum GetUsersResponse {
     /// List of users
     #[oai(status = 200)]
     Ok(Json<Vec<User>>),
+    /// Only admins can list users.
+    #[oai(status = 403)]
+    Forbidden,
 }
 
 #[derive(ApiResponse)]
@@ -80,6 +107,9 @@ pub enum FindUserResponse {
     /// User found.
     #[oai(status = 200)]
     Ok(Json<User>),
+    /// Not an admin, and not your own account.
+    #[oai(status = 403)]
+    Forbidden,
     /// User not found.
     #[oai(status = 404)]
     NotFound,
@@ -90,11 +120,24 @@ pub enum DeleteUserResponse {
     /// User successfully deleted.
     #[oai(status = 200)]
     Ok,
+    /// Only admins can delete users.
+    #[oai(status = 403)]
+    Forbidden,
     /// User not found.
     #[oai(status = 404)]
     NotFound,
 }
 
+#[derive(ApiResponse)]
+pub enum GetAuditLogResponse {
+    /// Audit log entries, newest first.
+    #[oai(status = 200)]
+    Ok(Json<Vec<AuditEntry>>),
+    /// Only admins can read the audit log.
+    #[oai(status = 403)]
+    Forbidden,
+}
+
 #[derive(ApiResponse)]
 pub enum LoginResponse {
     /// User successfully authenticated.
@@ -108,6 +151,14 @@ pub enum LoginResponse {
     /// Origin: header not valid.
     #[oai(status = 403)]
     BadOrigin,
+
+    /// Too many failed logins, try again later.
+    #[oai(status = 429)]
+    TooManyRequests(
+        /// Seconds to wait.
+        #[oai(header = "Retry-After")]
+        u64,
+    ),
 }
 
 #[derive(ApiResponse)]
@@ -120,14 +171,18 @@ pub enum LogoutResponse {
 impl Api {
     pub async fn create_user(
         &self,
-        _session: Session,
+        session: Session,
         user: CreateUser,
     ) -> Result<CreateUserResponse> {
+        if !session.admin {
+            return Ok(CreateUserResponse::Forbidden);
+        }
         let mut db_user = models::User {
             id: 0,
             username: user.username,
             password: user.password.0,
             email: user.email.map(|e| e.0),
+            admin: user.admin.unwrap_or(false),
         };
         let mut txn = self.state.db.handle.begin().await?;
         let id = db_user.insert(&mut txn).await?;
@@ -135,46 +190,42 @@ impl Api {
         Ok(CreateUserResponse::Ok(Json(id)))
     }
 
-    pub async fn get_users(&self, _session: Session) -> Result<GetUsersResponse> {
+    pub async fn get_users(&self, session: Session) -> Result<GetUsersResponse> {
+        if !session.admin {
+            return Ok(GetUsersResponse::Forbidden);
+        }
         let mut txn = self.state.db.handle.begin().await?;
-        let users = models::User::get_users(&mut txn)
-            .await?
-            .drain(..)
-            .map(|u| User {
-                id: u.id,
-                username: u.username,
-                email: u.email.map(|e| Email(e)),
-            })
-            .collect::<Vec<_>>();
+        let users =
+            models::User::get_users(&mut txn).await?.drain(..).map(User::from).collect::<Vec<_>>();
         Ok(GetUsersResponse::Ok(Json(users)))
     }
 
-    pub async fn find_user(&self, _session: Session, username: String) -> Result<FindUserResponse> {
+    pub async fn find_user(&self, session: Session, username: String) -> Result<FindUserResponse> {
+        if !session.admin && session.username != username {
+            return Ok(FindUserResponse::Forbidden);
+        }
         let mut txn = self.state.db.handle.begin().await?;
         match models::User::lookup(&mut txn, &username).await? {
-            Some(user) => {
-                let user = User {
-                    id: user.id,
-                    username: user.username,
-                    email: user.email.map(|e| Email(e)),
-                };
-                Ok(FindUserResponse::Ok(Json(user)))
-            },
+            Some(user) => Ok(FindUserResponse::Ok(Json(User::from(user)))),
             None => Ok(FindUserResponse::NotFound),
         }
     }
 
     pub async fn update_user(
         &self,
-        _session: Session,
+        session: Session,
         user_id: i64,
         user: UpdateUser,
     ) -> Result<UpdateUserResponse> {
+        if !session.admin && (session.user_id != user_id || user.admin.is_some()) {
+            return Ok(UpdateUserResponse::Forbidden);
+        }
         let db_user = models::UpdateUser {
             id: user_id,
             username: None,
             password: user.password.map(|p| p.0),
             email: user.email.map(|e| e.0),
+            admin: user.admin,
         };
         let mut txn = self.state.db.handle.begin().await?;
         let resp = match db_user.update(&mut txn).await? {
@@ -185,12 +236,31 @@ impl Api {
         Ok(resp)
     }
 
-    pub async fn delete_user(&self, _session: Session, user_id: i64) -> Result<DeleteUserResponse> {
+    pub async fn delete_user(&self, session: Session, user_id: i64) -> Result<DeleteUserResponse> {
+        if !session.admin {
+            return Ok(DeleteUserResponse::Forbidden);
+        }
         let mut txn = self.state.db.handle.begin().await?;
-        match models::User::delete(&mut txn, user_id).await? {
-            true => Ok(DeleteUserResponse::Ok),
-            false => Ok(DeleteUserResponse::NotFound),
+        let resp = match models::User::delete(&mut txn, user_id).await? {
+            true => DeleteUserResponse::Ok,
+            false => DeleteUserResponse::NotFound,
+        };
+        txn.commit().await?;
+        Ok(resp)
+    }
+
+    pub async fn get_audit_log(
+        &self,
+        session: Session,
+        offset: u32,
+        limit: u32,
+    ) -> Result<GetAuditLogResponse> {
+        if !session.admin {
+            return Ok(GetAuditLogResponse::Forbidden);
         }
+        let mut txn = self.state.db.handle.begin().await?;
+        let entries = AuditEntry::list(&mut txn, offset, limit).await?;
+        Ok(GetAuditLogResponse::Ok(Json(entries)))
     }
 
     pub async fn login(
@@ -220,18 +290,48 @@ impl Api {
             return Ok(Response::new(LoginResponse::BadOrigin));
         }
 
+        let addr = req.remote_addr().as_socket_addr().map(|a| a.ip());
+        let client = models::Client {
+            device: auth.device.clone(),
+            user_agent: req.header("user-agent").map(|ua| ua.to_string()),
+            remote_addr: addr.map(|a| a.to_string()),
+        };
+        let user = models::User::lookup(&mut txn, &auth.username).await?;
+
+        // Too many failed logins for this user or from this address?
+        // This counts the attempt as failed until it succeeds.
+        let throttle = &self.state.login_throttle;
+        let username = user.as_ref().map(|u| u.username.as_str());
+        if let Err(wait) = throttle.attempt(username, addr) {
+            log::info!("login: user {} throttled for {:?}", auth.username, wait);
+            let secs = ratelimit::retry_after(wait);
+            return Ok(Response::new(LoginResponse::TooManyRequests(secs)));
+        }
+
         // Find user.
-        let user = some_or_return!(models::User::lookup(&mut txn, &auth.username).await?, {
-            log::info!("login: user {} not found", auth.username);
-            Ok(Response::new(LoginResponse::NotFound))
-        });
+        let user = match user {
+            Some(user) => user,
+            None => {
+                log::info!("login: user {} not found", auth.username);
+                let event = AuditEvent::LoginFailed;
+                AuditEntry::insert(&mut txn, event, &auth.username, None, &client).await?;
+                txn.commit().await?;
+                return Ok(Response::new(LoginResponse::NotFound));
+            },
+        };
 
         // Verify password.
         let sha = user.password.starts_with("$6$");
         if (sha && !user.verify(&auth.password)) || (!sha && user.password != auth.password) {
             log::info!("login: user {} auth failed", auth.username);
+            let event = AuditEvent::LoginFailed;
+            AuditEntry::insert(&mut txn, event, &auth.username, Some(user.id), &client).await?;
+            txn.commit().await?;
             return Ok(Response::new(LoginResponse::NotFound));
         }
+        throttle.succeeded(&user.username, addr);
+        let event = AuditEvent::Login;
+        AuditEntry::insert(&mut txn, event, &auth.username, Some(user.id), &client).await?;
 
         // Re-use session if it exists.
         let mut session = None;
@@ -255,7 +355,8 @@ impl Api {
 
         if session.is_none() {
             // Create new session.
-            session = Some(Session::create(&mut txn, user.id, &user.username).await?);
+            let s = Session::create(&mut txn, user.id, &user.username, user.admin, &client).await?;
+            session = Some(s);
         }
 
         txn.commit().await?;
@@ -0,0 +1,136 @@
+//! BlurHash encoder, see <https://blurha.sh>.
+//!
+//! A blurhash is a short string that a client can decode into a blurry
+//! placeholder of an image, to show while the real image is loading.
+//!
+use std::f32::consts::PI;
+
+use image::RgbImage;
+
+const BASE83: &[u8] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz#$%*+,-.:;=?@[]^_{|}~";
+
+/// Encode an image as a blurhash, with `cx` by `cy` (1..=9) components.
+///
+/// This is O(width * height * cx * cy), so scale the image down first.
+pub fn encode(img: &RgbImage, cx: u32, cy: u32) -> String {
+    let (cx, cy) = (cx.clamp(1, 9), cy.clamp(1, 9));
+    let (width, height) = img.dimensions();
+    let pixels = linear_pixels(img);
+
+    let mut factors = Vec::with_capacity((cx * cy) as usize);
+    for j in 0..cy {
+        for i in 0..cx {
+            let norm = if i == 0 && j == 0 { 1.0 } else { 2.0 };
+            let mut factor = [0f32; 3];
+            for y in 0..height {
+                let basis_y = (PI * j as f32 * y as f32 / height as f32).cos();
+                for x in 0..width {
+                    let basis = basis_y * (PI * i as f32 * x as f32 / width as f32).cos();
+                    let pixel = pixels[(y * width + x) as usize];
+                    for (f, p) in factor.iter_mut().zip(pixel) {
+                        *f += basis * p;
+                    }
+                }
+            }
+            let scale = norm / (width * height).max(1) as f32;
+            factors.push(factor.map(|f| f * scale));
+        }
+    }
+    let (dc, ac) = factors.split_first().unwrap();
+
+    let mut hash = String::with_capacity(4 + 2 * factors.len());
+    push_base83(&mut hash, (cx - 1) + (cy - 1) * 9, 1);
+
+    let max_value = if ac.is_empty() {
+        push_base83(&mut hash, 0, 1);
+        1.0
+    } else {
+        let actual_max = ac.iter().flatten().fold(0f32, |m, v| m.max(v.abs()));
+        let quantised_max = (actual_max * 166.0 - 0.5).floor().clamp(0.0, 82.0) as u32;
+        push_base83(&mut hash, quantised_max, 1);
+        (quantised_max + 1) as f32 / 166.0
+    };
+
+    push_base83(&mut hash, encode_dc(dc), 4);
+    for factor in ac {
+        push_base83(&mut hash, encode_ac(factor, max_value), 2);
+    }
+    hash
+}
+
+/// Average colour of an image, as `#rrggbb`.
+pub fn average_color(img: &RgbImage) -> String {
+    let pixels = linear_pixels(img);
+    let mut sum = [0f32; 3];
+    for pixel in &pixels {
+        for (s, p) in sum.iter_mut().zip(pixel) {
+            *s += p;
+        }
+    }
+    let n = pixels.len().max(1) as f32;
+    format!("#{:06x}", encode_dc(&sum.map(|s| s / n)))
+}
+
+fn linear_pixels(img: &RgbImage) -> Vec<[f32; 3]> {
+    img.pixels().map(|p| p.0.map(srgb_to_linear)).collect()
+}
+
+fn encode_dc(value: &[f32; 3]) -> u32 {
+    let [r, g, b] = value.map(linear_to_srgb);
+    (r << 16) + (g << 8) + b
+}
+
+fn encode_ac(value: &[f32; 3], max_value: f32) -> u32 {
+    let [r, g, b] = value.map(|v| {
+        let v = v / max_value;
+        (v.signum() * v.abs().sqrt() * 9.0 + 9.5).floor().clamp(0.0, 18.0) as u32
+    });
+    r * 19 * 19 + g * 19 + b
+}
+
+fn srgb_to_linear(value: u8) -> f32 {
+    let v = value as f32 / 255.0;
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> u32 {
+    let v = value.clamp(0.0, 1.0);
+    if v <= 0.0031308 {
+        (v * 12.92 * 255.0 + 0.5) as u32
+    } else {
+        ((1.055 * v.powf(1.0 / 2.4) - 0.055) * 255.0 + 0.5) as u32
+    }
+}
+
+fn push_base83(hash: &mut String, value: u32, length: u32) {
+    for i in 1..=length {
+        let digit = (value / 83u32.pow(length - i)) % 83;
+        hash.push(BASE83[digit as usize] as char);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn solid_color() {
+        let img = RgbImage::from_pixel(8, 8, image::Rgb([255, 0, 0]));
+        assert_eq!(encode(&img, 1, 1), "00TI:j");
+        assert_eq!(&encode(&img, 4, 3)[..1], "L");
+        assert_eq!(&encode(&img, 4, 3)[2..6], "TI:j");
+        assert_eq!(average_color(&img), "#ff0000");
+    }
+
+    #[test]
+    fn dimensions() {
+        let img = RgbImage::from_fn(16, 8, |x, _| image::Rgb([(x * 16) as u8, 128, 0]));
+        assert_eq!(encode(&img, 4, 3).len(), 4 + 2 * 12);
+        assert_eq!(encode(&img, 1, 1).len(), 6);
+    }
+}
@@ -1,15 +1,17 @@
+use std::time::Duration;
+
 use anyhow::Result;
 use poem_openapi::{Enum, Object};
 use serde::{de, de::Error as _, Deserialize};
 
-#[derive(Deserialize, Enum, Debug, Default)]
+#[derive(Deserialize, Enum, Clone, Debug, Default)]
 pub enum CollectionType {
     #[default]
     Movies,
     TVShows,
 }
 
-#[derive(Deserialize, Object, Debug, Default)]
+#[derive(Deserialize, Object, Clone, Debug, Default)]
 pub struct Collection {
     #[serde(rename(deserialize = "__label__"))]
     pub name: String,
@@ -28,6 +30,14 @@ pub struct Collection {
     #[serde(default, skip)]
     #[oai(skip)]
     pub baseurl: String,
+
+    #[serde(rename = "scan-interval", default, with = "humantime_serde")]
+    #[oai(skip)]
+    pub scan_interval: Option<Duration>,
+
+    #[serde(default)]
+    #[oai(skip)]
+    pub watch: bool,
 }
 
 impl Collection {
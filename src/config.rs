@@ -13,6 +13,9 @@ use crate::collections::Collection;
 pub struct Server {
     #[serde(default)]
     pub cachedir: Option<String>,
+    /// Maximum size of the image cache in MB.
+    #[serde(rename = "cache-size", default)]
+    pub cache_size: Option<u64>,
     pub appdir: PathBuf,
     pub database: String,
     #[serde(default)]
@@ -25,6 +28,12 @@ pub struct Server {
     pub tls_listen: Vec<String>,
     #[serde(default)]
     pub hostname: Vec<String>,
+    /// Maximum requests per second per client address, on average.
+    #[serde(rename = "rate-limit", default)]
+    pub rate_limit: Option<f64>,
+    /// Maximum burst of requests per client address (default 100).
+    #[serde(rename = "rate-limit-burst", default)]
+    pub rate_limit_burst: Option<u32>,
 
     #[serde(default, skip)]
     pub addrs: Vec<SocketAddr>,
@@ -63,6 +72,9 @@ pub fn from_file(path: &str) -> anyhow::Result<Config> {
     {
         bail!("{}: must set tls_cert and tls_key", path);
     }
+    if cfg.server.rate_limit.map(|r| r <= 0.0).unwrap_or(false) {
+        bail!("{}: rate-limit must be larger than 0", path);
+    }
     for coll in &cfg.collections {
         coll.check().with_context(|| format!("file: {}", path))?;
     }
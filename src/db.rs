@@ -5,13 +5,16 @@
 ///
 use std::collections::HashMap;
 use std::io::ErrorKind;
+use std::str::FromStr;
 
 use anyhow::{Context, Result};
-use sqlx::sqlite::SqlitePool;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool};
 
 use crate::collections::Collection;
+use crate::events::{Event, EventType};
 use crate::jvec::JVec;
 use crate::kodifs::{self, scandirs};
+use crate::migrations;
 use crate::models::{MediaItem, UniqueId, UniqueIds};
 use crate::util::{Id, SystemTimeToUnixTime};
 
@@ -23,6 +26,16 @@ enum ItemType {
     TVShow,
 }
 
+/// Number of items added / updated / deleted by a collection scan.
+#[derive(Clone, Debug, Default)]
+pub struct ScanCounts {
+    pub added: u64,
+    pub updated: u64,
+    pub deleted: u64,
+    /// What was changed, to send to clients after the commit.
+    pub changes: Vec<Event>,
+}
+
 #[derive(Clone)]
 pub struct Db {
     pub handle: DbHandle,
@@ -30,40 +43,23 @@ pub struct Db {
 
 impl Db {
     pub async fn connect(db: &str) -> Result<Db> {
-        let db = Db { handle: SqlitePool::connect(db).await? };
-        db.set_mediaitem_sequence().await?;
+        let opts = SqliteConnectOptions::from_str(db)?.create_if_missing(true);
+        let db = Db {
+            handle: SqlitePool::connect_with(opts).await?,
+        };
+        migrations::run(&db.handle).await.context("failed to migrate database")?;
         Ok(db)
     }
 
-    async fn set_mediaitem_sequence(&self) -> Result<()> {
-        let mut txn = self.handle.begin().await?;
-
-        let r: Option<i64> = sqlx::query!(
-            r#"
-                SELECT seq as "seq!: i64" FROM sqlite_sequence WHERE name = 'mediaitems'
-            "#
-        )
-        .fetch_optional(&mut txn)
-        .await?
-        .map(|row| row.seq);
-
-        match r {
-            Some(r) if r < 1000 => {
-                sqlx::query!("UPDATE sqlite_sequence SET seq = 1000 WHERE name = 'mediaitems'")
-                    .execute(&mut txn)
-                    .await?;
-            },
-            Some(_) => {},
-            None => {
-                sqlx::query!("INSERT INTO sqlite_sequence(name, seq) VALUES('mediaitems', 1000)")
-                    .execute(&mut txn)
-                    .await?;
-            },
-        }
-
-        txn.commit().await?;
-
-        Ok(())
+    /// Open an existing database read-only. It is not created or migrated,
+    /// its schema must already be up to date.
+    pub async fn open(db: &str) -> Result<Db> {
+        let opts = SqliteConnectOptions::from_str(db)?.read_only(true);
+        let db = Db {
+            handle: SqlitePool::connect_with(opts).await?,
+        };
+        migrations::check(&db.handle).await?;
+        Ok(db)
     }
 
     // Update one movie.
@@ -72,6 +68,17 @@ impl Db {
         coll: &Collection,
         name: &str,
         txn: &mut TxnHandle<'_>,
+    ) -> Result<Option<Id>> {
+        let mut counts = ScanCounts::default();
+        self.do_update_mediaitem(coll, name, txn, &mut counts).await
+    }
+
+    async fn do_update_mediaitem(
+        &self,
+        coll: &Collection,
+        name: &str,
+        txn: &mut TxnHandle<'_>,
+        counts: &mut ScanCounts,
     ) -> Result<Option<Id>> {
         let mut need_update = false;
 
@@ -136,16 +143,27 @@ impl Db {
             item.insert(&mut *txn)
                 .await
                 .with_context(|| format!("failed to insert db for {}", name))?;
+            counts.added += 1;
+            counts.changes.push(Event::item(EventType::Added, &item));
         } else if item.lastmodified > old_lastmodified || need_update {
             // There was an update, so update the database.
             log::debug!("Db::update_mediaitem: updating item in the db: {}", name);
             item.update(&mut *txn)
                 .await
                 .with_context(|| format!("failed to update db for {}", name))?;
+            counts.updated += 1;
+            counts.changes.push(Event::item(EventType::Updated, &item));
         } else {
             log::trace!("Db::update_mediaitem: no update needed for: {}", name);
         }
 
+        // For tvshows, scan the episodes as well.
+        if item.type_ == "tvshow" {
+            self.update_episodes(coll, &item, &mut *txn, counts)
+                .await
+                .with_context(|| format!("failed to update episodes for {}", name))?;
+        }
+
         log::trace!("checking UniqueIds..");
         if let Some(nfo_lastmodified) = item.nfo_file.map(|f| f.modified) {
             if nfo_lastmodified.unixtime_ms() > old_lastmodified {
@@ -163,13 +181,45 @@ impl Db {
         Ok(Some(item.id))
     }
 
+    // Update the episodes of one tvshow.
+    async fn update_episodes(
+        &self,
+        coll: &Collection,
+        tvshow: &MediaItem,
+        txn: &mut TxnHandle<'_>,
+        counts: &mut ScanCounts,
+    ) -> Result<()> {
+        let db_episodes = MediaItem::get_episodes(&mut *txn, tvshow.id, true).await?;
+        for ep in kodifs::scan_episodes(coll, tvshow, db_episodes).await {
+            if ep.is_new {
+                log::debug!("Db::update_episodes: adding new episode: {}", ep.item.title);
+                ep.item.insert(&mut *txn).await?;
+                counts.added += 1;
+                counts.changes.push(Event::item(EventType::Added, &ep.item));
+            } else if ep.item.deleted {
+                log::debug!("Db::update_episodes: marking as deleted: {}", ep.item.title);
+                ep.item.update(&mut *txn).await?;
+                counts.deleted += 1;
+                counts.changes.push(Event::item(EventType::Deleted, &ep.item));
+            } else if ep.updated {
+                log::debug!("Db::update_episodes: updating episode: {}", ep.item.title);
+                ep.item.update(&mut *txn).await?;
+                counts.updated += 1;
+                counts.changes.push(Event::item(EventType::Updated, &ep.item));
+            }
+        }
+        Ok(())
+    }
+
     // Update a collection of movies / tvshows.
     //
-    // Returns Ok if we can commit, error if not.
-    pub async fn update_collection(&self, coll: &Collection) -> Result<()> {
+    // Returns the number of items added / updated / deleted if we
+    // could commit, error if not.
+    pub async fn update_collection(&self, coll: &Collection) -> Result<ScanCounts> {
+        let mut counts = ScanCounts::default();
         let r = async {
             let mut txn = self.handle.begin().await?;
-            match self.do_update_collection(coll, &mut txn).await {
+            match self.do_update_collection(coll, &mut txn, &mut counts).await {
                 Ok(()) => Ok(txn.commit().await?),
                 Err(e) => {
                     let _ = txn.rollback().await;
@@ -185,10 +235,15 @@ impl Db {
             return Err(e)?;
         }
 
-        Ok(())
+        Ok(counts)
     }
 
-    async fn do_update_collection(&self, coll: &Collection, txn: &mut TxnHandle<'_>) -> Result<()> {
+    async fn do_update_collection(
+        &self,
+        coll: &Collection,
+        txn: &mut TxnHandle<'_>,
+        counts: &mut ScanCounts,
+    ) -> Result<()> {
         // Get a list of directories from the filesystem.
         log::debug!("update_collection: scanning directory {}", coll.directory);
         let mut dirs = scandirs::scan_directories(coll, true).await;
@@ -216,6 +271,7 @@ impl Db {
                        0 AS "keep!: bool"
                 FROM mediaitems
                 WHERE collection_id = ?
+                  AND type != 'episode'
                   AND deleted != 1"#,
             coll.collection_id
         )
@@ -254,7 +310,7 @@ impl Db {
 
             // Ok, we have to do a full rescan of this item.
             // self.update_movie will only return an error for SQL errors.
-            if self.update_mediaitem(coll, &dbitem.dir, &mut *txn).await?.is_some() {
+            if self.do_update_mediaitem(coll, &dbitem.dir, &mut *txn, counts).await?.is_some() {
                 // successfully updated.
                 dbitem.keep = true;
             }
@@ -265,7 +321,7 @@ impl Db {
         log::trace!("adding new directories ({})", dirs.len());
         for dir in dirs.keys() {
             log::trace!("adding {}", dir);
-            if let Some(id) = self.update_mediaitem(coll, dir, &mut *txn).await? {
+            if let Some(id) = self.do_update_mediaitem(coll, dir, &mut *txn, counts).await? {
                 map.remove(&id);
             }
         }
@@ -274,20 +330,80 @@ impl Db {
         log::trace!("setting deleted flags ({})", map.iter().filter(|(_, v)| !v.keep).count());
         for (id, dbitem) in map.iter().filter(|(_, item)| !item.keep) {
             log::trace!("update_collection: marking as deleted: {}", dbitem.dir);
-            sqlx::query!(
+            let rows = sqlx::query!(
                 r#"
                     UPDATE mediaitems
                     SET deleted = 1
-                    WHERE id = ?"#,
+                    WHERE id = ? OR tvshow_id = ?
+                    RETURNING id AS "id!: Id", type AS "type_!: String""#,
+                *id,
                 *id
             )
-            .execute(&mut *txn)
+            .fetch_all(&mut *txn)
             .await?;
+            counts.deleted += 1;
+            for row in rows {
+                let event =
+                    Event::item_id(EventType::Deleted, coll.collection_id, row.id, &row.type_);
+                counts.changes.push(event);
+            }
         }
 
         Ok(())
     }
 
+    // Update a few items of a collection, by directory name.
+    //
+    // Directories that do not exist anymore are marked as deleted. If the
+    // item was renamed, the new directory will be matched with the old item
+    // by uniqueid, as in `update_mediaitem`.
+    pub async fn update_directories(
+        &self,
+        coll: &Collection,
+        dirs: &[String],
+    ) -> Result<ScanCounts> {
+        let mut counts = ScanCounts::default();
+        let mut txn = self.handle.begin().await?;
+
+        for dir in dirs {
+            let path = format!("{}/{}", coll.directory, dir);
+            let is_dir = tokio::fs::metadata(&path).await.map(|m| m.is_dir()).unwrap_or(false);
+            if is_dir {
+                self.do_update_mediaitem(coll, dir, &mut txn, &mut counts).await?;
+                continue;
+            }
+            log::trace!("update_directories: marking as deleted: {}", dir);
+            let rows = sqlx::query!(
+                r#"
+                    UPDATE mediaitems
+                    SET deleted = 1
+                    WHERE deleted = 0
+                      AND (id IN (
+                          SELECT id FROM mediaitems
+                          WHERE collection_id = ? AND json_extract(directory, '$.path') = ?)
+                        OR tvshow_id IN (
+                          SELECT id FROM mediaitems
+                          WHERE collection_id = ? AND json_extract(directory, '$.path') = ?))
+                    RETURNING id AS "id!: Id", type AS "type_!: String""#,
+                coll.collection_id,
+                dir,
+                coll.collection_id,
+                dir,
+            )
+            .fetch_all(&mut txn)
+            .await?;
+            counts.deleted += rows.len() as u64;
+            for row in rows {
+                let event =
+                    Event::item_id(EventType::Deleted, coll.collection_id, row.id, &row.type_);
+                counts.changes.push(event);
+            }
+        }
+
+        txn.commit().await?;
+        Ok(counts)
+    }
+
     // Lookup a movie or tvshow in the database and return it's ID.
     pub async fn lookup(&self, by: &FindItemBy<'_>) -> Result<Option<Id>> {
         let mut txn = self.handle.begin().await?;
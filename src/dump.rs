@@ -0,0 +1,475 @@
+//! Dump the database as JSON, and import it again.
+//!
+//! Every row is written as one `Record`, tagged with the name of the
+//! table it came from. Records are written in an order that can be
+//! replayed into an empty database: users and mediaitems first, then
+//! the tables that refer to them.
+//!
+use std::collections::HashSet;
+use std::io::{BufRead, Write};
+
+use anyhow::{Context, Result};
+use futures_util::TryStreamExt;
+use serde::{Deserialize, Serialize};
+
+use crate::db::{Db, TxnHandle};
+use crate::jvec::JVec;
+use crate::models::{FileInfo, MediaItem, Nfo, Seen, Subtitle, Thumb, User, Video};
+use crate::util::Id;
+
+/// One row of one table.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "table", rename_all = "lowercase")]
+pub enum Record {
+    Users(UserRow),
+    Mediaitems(Box<MediaItem>),
+    Uniqueids(UniqueIdRow),
+    Images(ImageRow),
+    Seen(SeenRow),
+}
+
+/// A user, without the password hash.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UserRow {
+    pub id: i64,
+    pub username: String,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub admin: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UniqueIdRow {
+    pub mediaitem_id: Id,
+    pub idtype: String,
+    pub uniqueid: String,
+    pub is_default: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ImageRow {
+    pub id: i64,
+    pub collection_id: i64,
+    pub mediaitem_id: Id,
+    pub image_id: i64,
+    pub fileinfo: FileInfo,
+    pub aspect: String,
+    pub width: i64,
+    pub height: i64,
+    pub quality: i64,
+    pub accessed: i64,
+    #[serde(default = "default_format")]
+    pub format: String,
+    #[serde(default)]
+    pub alpha: bool,
+}
+
+fn default_format() -> String {
+    "jpg".to_string()
+}
+
+/// Watch state of one item, for one user.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SeenRow {
+    pub user_id: i64,
+    #[serde(flatten)]
+    pub seen: Seen,
+}
+
+/// Which mediaitems to dump.
+#[derive(Default, Debug)]
+pub struct DumpFilter {
+    pub collection_id: Option<u32>,
+    pub type_: Option<String>,
+    pub deleted_too: bool,
+}
+
+/// Dump the database as pretty JSON (an array of records) or as
+/// newline-delimited JSON (one record per line).
+///
+/// Records are written as they are read from the database.
+pub async fn dump(db: &Db, filter: &DumpFilter, ndjson: bool, out: impl Write) -> Result<()> {
+    let mut writer = RecordWriter {
+        out: std::io::BufWriter::new(out),
+        ndjson,
+        count: 0,
+    };
+    let mut txn = db.handle.begin().await?;
+    write_records(&mut txn, filter, &mut writer).await?;
+    txn.commit().await?;
+    writer.finish()
+}
+
+struct RecordWriter<W: Write> {
+    out: std::io::BufWriter<W>,
+    ndjson: bool,
+    count: u64,
+}
+
+impl<W: Write> RecordWriter<W> {
+    fn write(&mut self, record: &Record) -> Result<()> {
+        if self.ndjson {
+            serde_json::to_writer(&mut self.out, record)?;
+            self.out.write_all(b"\n")?;
+        } else {
+            self.out.write_all(if self.count == 0 { b"[\n" } else { b",\n" })?;
+            serde_json::to_writer_pretty(&mut self.out, record)?;
+        }
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<()> {
+        if !self.ndjson {
+            self.out.write_all(if self.count == 0 { b"[]\n" } else { b"\n]\n" })?;
+        }
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+async fn write_records<W: Write>(
+    txn: &mut TxnHandle<'_>,
+    filter: &DumpFilter,
+    writer: &mut RecordWriter<W>,
+) -> Result<()> {
+    let mut users = sqlx::query_as!(
+        UserRow,
+        r#"SELECT id, username, email, admin AS "admin!: bool" FROM users ORDER BY id"#
+    )
+    .fetch(&mut *txn);
+    while let Some(user) = users.try_next().await? {
+        writer.write(&Record::Users(user))?;
+    }
+    drop(users);
+
+    let mut items = sqlx::query_as!(
+        MediaItem,
+        r#"
+            SELECT id AS "id: Id",
+                   type AS "type_",
+                   collection_id AS "collection_id: u32",
+                   lastmodified,
+                   dateadded,
+                   directory AS "directory?: FileInfo",
+                   deleted AS "deleted!: bool",
+                   title AS "title!: String",
+                   year AS "year?: u32",
+                   nfo_file AS "nfo_file?: FileInfo",
+                   nfo_info AS "nfo_info?: Nfo",
+                   thumbs AS "thumbs!: JVec<Thumb>",
+                   subtitles AS "subtitles!: JVec<Subtitle>",
+                   video_file AS "video_file?: FileInfo",
+                   video_info AS "video_info?: Video",
+                   season AS "season?: u32",
+                   episode AS "episode?: u32",
+                   episode_last AS "episode_last?: u32",
+                   tvshow_id AS "tvshow_id?: Id"
+            FROM mediaitems
+            WHERE (? IS NULL OR collection_id = ?)
+              AND (? IS NULL OR type = ?)
+              AND (deleted = 0 OR deleted = ?)
+            ORDER BY collection_id, type DESC, tvshow_id, season, episode, id"#,
+        filter.collection_id,
+        filter.collection_id,
+        filter.type_,
+        filter.type_,
+        filter.deleted_too,
+    )
+    .fetch(&mut *txn);
+    let mut ids = HashSet::new();
+    while let Some(item) = items.try_next().await? {
+        ids.insert(item.id);
+        writer.write(&Record::Mediaitems(Box::new(item)))?;
+    }
+    drop(items);
+
+    let mut uniqueids = sqlx::query_as!(
+        UniqueIdRow,
+        r#"
+            SELECT mediaitem_id AS "mediaitem_id!: Id",
+                   idtype,
+                   uniqueid,
+                   is_default AS "is_default!: bool"
+            FROM uniqueids
+            ORDER BY mediaitem_id, idtype"#,
+    )
+    .fetch(&mut *txn);
+    while let Some(u) = uniqueids.try_next().await? {
+        if ids.contains(&u.mediaitem_id) {
+            writer.write(&Record::Uniqueids(u))?;
+        }
+    }
+    drop(uniqueids);
+
+    let mut images = sqlx::query_as!(
+        ImageRow,
+        r#"
+            SELECT id,
+                   collection_id,
+                   mediaitem_id AS "mediaitem_id!: Id",
+                   image_id,
+                   fileinfo AS "fileinfo!: FileInfo",
+                   aspect,
+                   width,
+                   height,
+                   quality,
+                   accessed,
+                   format,
+                   alpha AS "alpha!: bool"
+            FROM images
+            ORDER BY id"#,
+    )
+    .fetch(&mut *txn);
+    while let Some(i) = images.try_next().await? {
+        if ids.contains(&i.mediaitem_id) {
+            writer.write(&Record::Images(i))?;
+        }
+    }
+    drop(images);
+
+    let mut seen = sqlx::query!(
+        r#"
+            SELECT user_id,
+                   mediaitem_id AS "mediaitem_id!: Id",
+                   position,
+                   duration,
+                   completed AS "completed!: bool",
+                   updated
+            FROM seen
+            ORDER BY user_id, mediaitem_id"#,
+    )
+    .fetch(&mut *txn);
+    while let Some(s) = seen.try_next().await? {
+        if !ids.contains(&s.mediaitem_id) {
+            continue;
+        }
+        writer.write(&Record::Seen(SeenRow {
+            user_id: s.user_id,
+            seen: Seen {
+                mediaitem_id: s.mediaitem_id,
+                position: s.position,
+                duration: s.duration,
+                completed: s.completed,
+                updated: s.updated,
+            },
+        }))?;
+    }
+
+    Ok(())
+}
+
+/// Number of rows imported, per table.
+#[derive(Default, Debug)]
+pub struct ImportCounts {
+    pub users: u64,
+    pub mediaitems: u64,
+    pub uniqueids: u64,
+    pub images: u64,
+    pub seen: u64,
+}
+
+/// Import a newline-delimited JSON dump into an empty database.
+///
+/// The dump does not contain password hashes. If `password` is set, all
+/// users get that password, otherwise they will not be able to log in
+/// until their password has been reset.
+pub async fn import(db: &Db, input: impl BufRead, password: Option<&str>) -> Result<ImportCounts> {
+    let mut counts = ImportCounts::default();
+    let mut txn = db.handle.begin().await?;
+
+    let row = sqlx::query!(
+        r#"
+            SELECT (SELECT COUNT(*) FROM mediaitems) +
+                   (SELECT COUNT(*) FROM users) AS "count!: i64""#
+    )
+    .fetch_one(&mut txn)
+    .await?;
+    if row.count > 0 {
+        bail!("import: database is not empty");
+    }
+
+    // Images refer to other images, so check the foreign keys at commit time.
+    sqlx::query("PRAGMA defer_foreign_keys = ON").execute(&mut txn).await?;
+
+    let hashed = match password {
+        Some(password) => User::hash_password(password)?,
+        None => String::from("!"),
+    };
+
+    for (lineno, line) in input.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: Record = serde_json::from_str(&line)
+            .map_err(|e| anyhow!("import: line {}: {}", lineno + 1, e))?;
+        import_record(&mut txn, record, &hashed, &mut counts)
+            .await
+            .with_context(|| format!("import: line {}", lineno + 1))?;
+    }
+
+    txn.commit().await?;
+    Ok(counts)
+}
+
+async fn import_record(
+    txn: &mut TxnHandle<'_>,
+    record: Record,
+    hashed: &str,
+    counts: &mut ImportCounts,
+) -> Result<()> {
+    match record {
+        Record::Users(u) => {
+            sqlx::query!(
+                r#"
+                    INSERT INTO users(id, username, password, email, admin)
+                    VALUES(?, ?, ?, ?, ?)"#,
+                u.id,
+                u.username,
+                hashed,
+                u.email,
+                u.admin,
+            )
+            .execute(&mut *txn)
+            .await?;
+            counts.users += 1;
+        },
+        Record::Mediaitems(item) => {
+            item.insert(&mut *txn).await?;
+            counts.mediaitems += 1;
+        },
+        Record::Uniqueids(u) => {
+            sqlx::query!(
+                r#"
+                    INSERT INTO uniqueids(mediaitem_id, idtype, uniqueid, is_default)
+                    VALUES(?, ?, ?, ?)"#,
+                u.mediaitem_id,
+                u.idtype,
+                u.uniqueid,
+                u.is_default,
+            )
+            .execute(&mut *txn)
+            .await?;
+            counts.uniqueids += 1;
+        },
+        Record::Images(i) => {
+            sqlx::query!(
+                r#"
+                    INSERT INTO images(id, collection_id, mediaitem_id, image_id, fileinfo,
+                                       aspect, width, height, quality, accessed, format,
+                                       alpha)
+                    VALUES(?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#,
+                i.id,
+                i.collection_id,
+                i.mediaitem_id,
+                i.image_id,
+                i.fileinfo,
+                i.aspect,
+                i.width,
+                i.height,
+                i.quality,
+                i.accessed,
+                i.format,
+                i.alpha,
+            )
+            .execute(&mut *txn)
+            .await?;
+            counts.images += 1;
+        },
+        Record::Seen(s) => {
+            let seen = s.seen;
+            sqlx::query!(
+                r#"
+                    INSERT INTO seen(user_id, mediaitem_id, position, duration, completed, updated)
+                    VALUES(?, ?, ?, ?, ?, ?)"#,
+                s.user_id,
+                seen.mediaitem_id,
+                seen.position,
+                seen.duration,
+                seen.completed,
+                seen.updated,
+            )
+            .execute(&mut *txn)
+            .await?;
+            counts.seen += 1;
+        },
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn seed(db: &Db) {
+        let mut txn = db.handle.begin().await.unwrap();
+        sqlx::query(
+            "INSERT INTO users(id, username, password, email, admin) VALUES(3, 'u', 'x', 'u@x', 1)",
+        )
+        .execute(&mut txn)
+        .await
+        .unwrap();
+        let item = MediaItem {
+            type_: "movie".to_string(),
+            id: Id::from_str("m1").unwrap(),
+            collection_id: 1,
+            dateadded: "2022-01-01".to_string(),
+            title: "Movie".to_string(),
+            year: Some(2022),
+            ..MediaItem::default()
+        };
+        item.insert(&mut txn).await.unwrap();
+        sqlx::query(
+            "INSERT INTO uniqueids(mediaitem_id, idtype, uniqueid, is_default) VALUES('m1', 'imdb', 'tt1', 1)",
+        )
+        .execute(&mut txn)
+        .await
+        .unwrap();
+        let fileinfo = r#"{"path":"poster.png","inode":1,"size":2,"modified":{"secs_since_epoch":3,"nanos_since_epoch":0}}"#;
+        sqlx::query(
+            r#"
+                INSERT INTO images(id, collection_id, mediaitem_id, image_id, fileinfo,
+                                   aspect, width, height, quality, accessed, format, alpha)
+                VALUES(7, 1, 'm1', 7, ?, 'poster', 600, 900, 100, 5, 'png', 1)"#,
+        )
+        .bind(fileinfo)
+        .execute(&mut txn)
+        .await
+        .unwrap();
+        sqlx::query(
+            r#"
+                INSERT INTO seen(user_id, mediaitem_id, position, duration, completed, updated)
+                VALUES(3, 'm1', 12.5, 100.0, 0, 1000)"#,
+        )
+        .execute(&mut txn)
+        .await
+        .unwrap();
+        txn.commit().await.unwrap();
+    }
+
+    async fn dump_ndjson(db: &Db) -> String {
+        let mut out = Vec::new();
+        dump(db, &DumpFilter::default(), true, &mut out).await.unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[tokio::test]
+    async fn dump_and_import() {
+        let db = Db::connect("sqlite::memory:").await.unwrap();
+        seed(&db).await;
+        let dumped = dump_ndjson(&db).await;
+        assert_eq!(dumped.lines().count(), 5);
+
+        let db2 = Db::connect("sqlite::memory:").await.unwrap();
+        let counts = import(&db2, dumped.as_bytes(), None).await.unwrap();
+        assert_eq!(
+            (counts.users, counts.mediaitems, counts.uniqueids, counts.images, counts.seen),
+            (1, 1, 1, 1, 1)
+        );
+        assert_eq!(dump_ndjson(&db2).await, dumped);
+
+        // Importing into a database that is not empty fails.
+        assert!(import(&db2, dumped.as_bytes(), None).await.is_err());
+    }
+}
@@ -0,0 +1,138 @@
+//! Events that are pushed to clients.
+//!
+//! Scans send an event for every movie, tvshow or episode that was added,
+//! updated or deleted, and when they start and finish. Watch progress
+//! updates send an event to the other sessions of the same user.
+//!
+use poem_openapi::{Enum, Object};
+use tokio::sync::broadcast;
+
+use crate::models::{MediaItem, Session};
+use crate::util::Id;
+
+// Number of events a slow client can fall behind.
+const CAPACITY: usize = 256;
+
+/// What happened.
+#[derive(Enum, Clone, Copy, Debug, PartialEq)]
+#[oai(rename_all = "snake_case")]
+pub enum EventType {
+    /// An item was added.
+    Added,
+    /// An item was updated.
+    Updated,
+    /// An item was deleted.
+    Deleted,
+    /// A scan of a collection started.
+    ScanStarted,
+    /// A scan of a collection finished.
+    ScanFinished,
+    /// The watch progress of an item changed.
+    Seen,
+    /// Events were lost, the client should sync.
+    Resync,
+}
+
+/// An event.
+#[derive(Object, Clone, Debug)]
+pub struct Event {
+    /// What happened.
+    pub event: EventType,
+    /// Collection id.
+    pub collection_id: Option<u32>,
+    /// Movie, tvshow or episode id.
+    #[oai(read_only)]
+    pub id: Option<Id>,
+    /// "movie", "tvshow" or "episode".
+    #[oai(rename = "type")]
+    pub type_: Option<String>,
+    // Only for this user, but not for this session.
+    #[oai(skip)]
+    pub user_id: Option<i64>,
+    #[oai(skip)]
+    pub sessionid: Option<String>,
+}
+
+impl Event {
+    pub fn new(event: EventType) -> Event {
+        Event {
+            event,
+            collection_id: None,
+            id: None,
+            type_: None,
+            user_id: None,
+            sessionid: None,
+        }
+    }
+
+    /// A movie, tvshow or episode was added, updated or deleted.
+    pub fn item(event: EventType, item: &MediaItem) -> Event {
+        Event::item_id(event, item.collection_id, item.id, &item.type_)
+    }
+
+    pub fn item_id(event: EventType, collection_id: u32, id: Id, type_: &str) -> Event {
+        Event {
+            collection_id: Some(collection_id),
+            id: Some(id),
+            type_: Some(type_.to_string()),
+            ..Event::new(event)
+        }
+    }
+
+    /// A scan of a collection started or finished.
+    pub fn scan(event: EventType, collection_id: u32) -> Event {
+        Event {
+            collection_id: Some(collection_id),
+            ..Event::new(event)
+        }
+    }
+
+    /// The watch progress of an item was changed by this session.
+    pub fn seen(session: &Session, id: Id, type_: &str) -> Event {
+        Event {
+            id: Some(id),
+            type_: Some(type_.to_string()),
+            user_id: Some(session.user_id),
+            sessionid: Some(session.sessionid.clone()),
+            ..Event::new(EventType::Seen)
+        }
+    }
+
+    /// Should this event be sent to this session.
+    pub fn is_for(&self, session: &Session) -> bool {
+        match self.user_id {
+            Some(user_id) => {
+                user_id == session.user_id && self.sessionid.as_ref() != Some(&session.sessionid)
+            },
+            None => true,
+        }
+    }
+}
+
+/// Broadcast events to all listeners.
+#[derive(Clone)]
+pub struct Events {
+    tx: broadcast::Sender<Event>,
+}
+
+impl Events {
+    pub fn new() -> Events {
+        let (tx, _) = broadcast::channel(CAPACITY);
+        Events { tx }
+    }
+
+    /// Send an event. It is dropped if nobody is listening.
+    pub fn send(&self, event: Event) {
+        let _ = self.tx.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.tx.subscribe()
+    }
+}
+
+impl Default for Events {
+    fn default() -> Events {
+        Events::new()
+    }
+}
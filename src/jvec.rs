@@ -38,6 +38,12 @@ impl<T> DerefMut for JVec<T> {
     }
 }
 
+impl<T: PartialEq> PartialEq<Vec<T>> for JVec<T> {
+    fn eq(&self, other: &Vec<T>) -> bool {
+        &self.0 == other
+    }
+}
+
 impl<T> AsRef<JVec<T>> for JVec<T> {
     fn as_ref(&self) -> &JVec<T> {
         self
@@ -1,156 +1,150 @@
 use chrono::TimeZone;
 
+use super::resource::{is_related_file, ItemType, MediaData};
 use super::*;
 use crate::collections::Collection;
-use crate::models::{self, FileInfo, Thumb};
+use crate::models::{FileInfo, MediaItem, ThumbState};
 use crate::util::{Id, SystemTimeToUnixTime};
 
+/// An episode, as found on the filesystem.
 #[derive(Debug)]
-pub struct Episode<'c> {
-    pub showdir: String,
-    pub basepath: String,
-    pub files: Vec<String>,
-    pub episode: models::Episode,
-    pub coll: &'c Collection,
+pub struct ScannedEpisode {
+    pub item: Box<MediaItem>,
+    /// Not in the database yet.
+    pub is_new: bool,
+    /// Needs to be updated in the database.
+    pub updated: bool,
 }
 
-impl<'c> Episode<'c> {
-    pub async fn new<'a>(
-        coll: &'a Collection,
-        showdir: String,
-        name: &str,
-        basepath: &str,
-        season_hint: Option<u32>,
-        db_episode: Option<&mut models::Episode>,
-    ) -> Option<Episode<'a>> {
-        // Parse the episode filename for season and episode number etc.
-        let ep_info = match EpisodeNameInfo::parse(basepath, season_hint) {
-            Some(ep_info) => ep_info,
-            None => return None,
-        };
+/// Scan the episodes of a tvshow.
+///
+/// Episodes are the videos in the show directory and its (season)
+/// subdirectories. The episodes that are already in the database
+/// are matched by video filename, or if the file was renamed, by
+/// season and episode number. Episodes that are in the database but
+/// that were not found anymore are returned as `deleted`.
+pub async fn scan_episodes(
+    coll: &Collection,
+    tvshow: &MediaItem,
+    mut db_episodes: Vec<MediaItem>,
+) -> Vec<ScannedEpisode> {
+    let mut episodes = Vec::new();
+
+    let showdir = match tvshow.directory.as_ref() {
+        Some(dir) => format!("{}/{}", coll.directory, dir.path),
+        None => return episodes,
+    };
+    let mut entries = Vec::new();
+    if scandirs::read_dir(&showdir, true, &mut entries, false).await.is_err() {
+        return episodes;
+    }
 
-        // Must be able to open it.
-        let video = match FileInfo::from_path(&showdir, name).await {
-            Ok(v) => v,
-            Err(_) => return None,
+    for video in entries.iter().filter(|e| e.ends_with(".mp4")) {
+        let basename = video.strip_suffix(".mp4").unwrap();
+        let season_hint = season_hint(basename);
+        let ep_info = match EpisodeNameInfo::parse(video, season_hint) {
+            Some(ep_info) => ep_info,
+            None => continue,
         };
 
-        let mut episode = models::Episode {
-            id: Id::new(),
-            ..models::Episode::default()
+        // Find the episode in the list of episodes from the database.
+        let idx = db_episodes
+            .iter()
+            .position(|e| e.video_file.as_ref().map(|f| f.path.as_str()) == Some(video))
+            .or_else(|| {
+                db_episodes.iter().position(|e| {
+                    e.season == Some(ep_info.season) && e.episode == Some(ep_info.episode)
+                })
+            });
+        let is_new = idx.is_none();
+        let mut item = match idx {
+            Some(idx) => Box::new(db_episodes.remove(idx)),
+            None => Box::new(MediaItem {
+                id: Id::new(),
+                collection_id: coll.collection_id,
+                ..MediaItem::default()
+            }),
         };
-        if let Some(db_episode) = db_episode {
-            std::mem::swap(&mut episode, db_episode);
-            db_episode.deleted = true;
-            episode.deleted = false;
+        let mut updated = item.deleted
+            || item.tvshow_id != Some(tvshow.id)
+            || item.season != Some(ep_info.season)
+            || item.episode != Some(ep_info.episode)
+            || item.episode_last != ep_info.episode_last;
+        item.deleted = false;
+        item.tvshow_id = Some(tvshow.id);
+        item.season = Some(ep_info.season);
+        item.episode = Some(ep_info.episode);
+        item.episode_last = ep_info.episode_last;
+        item.directory = None;
+
+        // Thumbs and subtitles that are not found again are deleted.
+        for thumb in item.thumbs.iter_mut() {
+            thumb.state = ThumbState::Deleted;
         }
-        let modified = video.modified.unixtime_ms();
-        if let chrono::LocalResult::Single(c) = chrono::Local.timestamp_millis_opt(modified) {
-            episode.dateadded = Some(c.format("%Y-%m-%d").to_string());
+        for sub in item.subtitles.iter_mut() {
+            sub.state = ThumbState::Deleted;
         }
-        episode.lastmodified = modified;
-        episode.video = video;
-
-        // Add info from the filename.
-        episode.nfo_base.title = Some(ep_info.name);
-        episode.season = ep_info.season;
-        episode.episode = ep_info.episode;
-        // XXX TODO episode.double = ep_info.double;
-
-        Some(Episode {
-            showdir,
-            basepath: basepath.to_string(),
-            files: Vec::new(),
-            episode,
-            coll,
-        })
-    }
 
-    pub async fn finalize(mut self) -> Option<models::Episode> {
-        let mut files = std::mem::replace(&mut self.files, Vec::new());
-        for name in files.drain(..) {
-            self.add_related_file(name).await;
-        }
-        if !self.episode.nfo_base.nfo_type.is_episode() {
-            return None;
-        }
-        Some(self.episode)
-    }
-
-    // See if this is a file that is related to an episode, by
-    // indexing on the basepath.
-    async fn add_related_file(&mut self, name: String) {
-        let caps = IS_RELATED.captures(&name);
-        let (aux, ext) = match caps.as_ref() {
-            Some(c) => (c.get(2), &c[3]),
-            None => return,
+        let mut mediadata = MediaData {
+            basedir: showdir.clone(),
+            basename: basename.to_string(),
+            item_type: ItemType::Episode,
+            updated: false,
+            item,
         };
-        let aux = aux.map_or("", |m| m.as_str());
-
-        // Thumb: <base>.tbn or <base>-thumb.ext
-        if IS_IMAGE.is_match(&name) {
-            if ext == "tbn" || aux == "thumb" {
-                if let Err(e) = Thumb::add(
-                    &mut self.episode.thumbs,
-                    &self.showdir,
-                    &name,
-                    self.coll,
-                    self.episode.id,
-                    "thumb",
-                    None,
-                )
-                .await
-                {
-                    log::debug!("Episode::add_related_file: {}/{}: {}", self.showdir, name, e);
-                }
-            }
-            return;
-        }
 
-        /* // XXX FIXME subtitles.
-        if ext == "srt" {
-            if aux == "" || aux == "und" {
-                aux = "zz".to_string();
-            }
-            ep.srt_subs.push(Subs{
-                lang: aux,
-                path: p,
-            });
-            return;
+        // Add the video first, then the related files: <basename>.ext or <basename>-aspect.ext
+        if mediadata.add_file(video).await.is_err() {
+            continue;
         }
-
-        if ext == "vtt" {
-            if aux == "" || aux == "und" {
-                aux = "zz".to_string();
+        let related = entries.iter().filter(|e| is_related_file(basename, e));
+        let mut newest = 0;
+        for name in related {
+            if let Ok(fileinfo) = FileInfo::from_path(&showdir, name).await {
+                newest = std::cmp::max(newest, fileinfo.modified.unixtime_ms());
+            }
+            if name != video {
+                let _ = mediadata.add_file(name).await;
             }
-            ep.vtt_subs.push(Subs{
-                lang: aux,
-                path: p,
-            });
-            return;
         }
-        */
-
-        if ext == "nfo" {
-            match FileInfo::open(&self.showdir, &name).await {
-                Ok((mut file, nfofile)) => {
-                    let mut nfofile = Some(nfofile);
-                    if self.episode.nfofile == nfofile {
-                        // No change.
-                        nfofile = None;
-                    }
-
-                    if nfofile.is_some() {
-                        if let Ok(nfo) = Nfo::read(&mut file).await {
-                            nfo.update_episode(&mut self.episode);
-                            self.episode.nfofile = nfofile;
-                        }
-                    }
-                },
-                Err(_) => {},
+        updated |= mediadata.finalize();
+
+        let mut item = mediadata.item;
+        let title = item.nfo_info.as_ref().and_then(|n| n.title.clone());
+        item.title = title.unwrap_or(ep_info.name);
+        if item.dateadded == "" {
+            if let chrono::LocalResult::Single(c) = chrono::Local.timestamp_millis_opt(newest) {
+                item.dateadded = c.format("%Y-%m-%d").to_string();
             }
         }
+        item.lastmodified = newest;
+
+        episodes.push(ScannedEpisode { item, is_new, updated });
+    }
+
+    // What's left over was deleted.
+    for mut item in db_episodes.into_iter().filter(|e| !e.deleted) {
+        item.deleted = true;
+        episodes.push(ScannedEpisode {
+            item: Box::new(item),
+            is_new: false,
+            updated: true,
+        });
+    }
+
+    episodes
+}
+
+// If the episode is in a subdirectory named "Season 1", "S01", "Specials" etc,
+// use that as a hint for the season.
+fn season_hint(basename: &str) -> Option<u32> {
+    let (subdir, _) = basename.rsplit_once('/')?;
+    let subdir = subdir.to_lowercase();
+    if subdir == "specials" {
+        return Some(0);
     }
+    let num = subdir.strip_prefix("season").or_else(|| subdir.strip_prefix("s"))?;
+    num.trim_start_matches(|c| c == ' ' || c == '_' || c == '.').parse::<u32>().ok()
 }
 
 #[derive(Default, Debug)]
@@ -158,7 +152,7 @@ struct EpisodeNameInfo {
     name: String,
     season: u32,
     episode: u32,
-    double: bool,
+    episode_last: Option<u32>,
 }
 
 // Straight from the documentation of once_cell.
@@ -183,13 +177,13 @@ impl EpisodeNameInfo {
             return Some(ep);
         }
 
-        // pattern: ___.s03e04e05.___ or ___.s03e04-e05.___
-        const PAT2: &'static str = r#"^.*[. _][sS]([0-9]+)[eE]([0-9]+)-?[eE]([0-9]+)[. _].*$"#;
+        // pattern: ___.s03e04e05.___ or ___.s03e04-e05.___ (or s03e04e05e06 etc)
+        const PAT2: &'static str = r#"^.*[. _][sS]([0-9]+)[eE]([0-9]+)(?:-?[eE]([0-9]+))+[. _].*$"#;
         if let Some(caps) = regex!(PAT2).captures(name) {
             ep.name = format!("{}x{}-{}", &caps[1], &caps[2], &caps[3]);
             ep.season = caps[1].parse::<u32>().unwrap_or(0);
             ep.episode = caps[2].parse::<u32>().unwrap_or(0);
-            ep.double = true;
+            ep.episode_last = caps[3].parse::<u32>().ok().filter(|&e| e > ep.episode);
             return Some(ep);
         }
 
@@ -5,7 +5,7 @@ use url::Url;
 use crate::collections::{Collection, CollectionType};
 use crate::models;
 
-// mod episode;
+mod episode;
 mod movie;
 pub(crate) mod nfo;
 pub mod resource;
@@ -13,6 +13,7 @@ pub mod scandirs;
 mod tvshow;
 mod video;
 
+pub use episode::{scan_episodes, ScannedEpisode};
 pub use movie::scan_movie_dir;
 pub use nfo::Nfo;
 pub use tvshow::scan_tvshow_dir;
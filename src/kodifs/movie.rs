@@ -3,7 +3,7 @@ use std::time::SystemTime;
 
 use super::*;
 use crate::collections::*;
-use crate::models::{FileInfo, MediaItem, Thumb};
+use crate::models::{FileInfo, MediaItem, Thumb, ThumbState};
 use crate::util::{Id, SystemTimeToUnixTime};
 use super::resource::{ItemType, MediaData};
 
@@ -78,6 +78,13 @@ pub async fn scan_movie_dir(
         item: movie,
     };
 
+    // Subtitles that are not found again are deleted.
+    if !only_nfo {
+        for sub in mediadata.item.subtitles.iter_mut() {
+            sub.state = ThumbState::Deleted;
+        }
+    }
+
     // Then add all files.
     for entry in &entries {
         if only_nfo && !entry.ends_with(".nfo") {
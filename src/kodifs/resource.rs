@@ -21,6 +21,29 @@ const ASPECTS: &'static [&'static str] = &[
     "thumb",
 ];
 
+/// Is `name` a file that belongs with the video `<basename>.mp4`?
+///
+/// That is `<basename>.<ext>`, `<basename>-<aspect>.<ext>` for images, or
+/// `<basename>.<flags>.<ext>` for subtitles. Not `<basename>-e02.mp4`,
+/// which is another (multi-episode) video.
+pub fn is_related_file(basename: &str, name: &str) -> bool {
+    let rest = match name.strip_prefix(basename) {
+        Some(rest) => rest,
+        None => return false,
+    };
+    if let Some(rest) = rest.strip_prefix('-') {
+        return match rest.split_once('.') {
+            Some((aspect, ext)) => ASPECTS.contains(&aspect) && THUMBS.contains(&ext),
+            None => false,
+        };
+    }
+    match rest.strip_prefix('.').map(|r| r.rsplit_once('.')) {
+        Some(None) => true,
+        Some(Some((_, ext))) => SUBTITLES.contains(&ext),
+        None => false,
+    }
+}
+
 #[derive(PartialEq)]
 pub enum ItemType {
     Movie,
@@ -141,6 +164,12 @@ impl MediaData {
             if season_name.is_none() {
                 return Ok(());
             }
+        } else if self.item_type == ItemType::Episode && name_noext == self.basename {
+            // <basename>.tbn is the episode thumbnail.
+            if !filename.ends_with(".tbn") {
+                return Ok(());
+            }
+            aspect = "thumb";
         } else {
             // must be an image that starts with "basename-".
             if !filename.starts_with(&self.basename) {
@@ -171,11 +200,41 @@ impl MediaData {
         Ok(())
     }
 
-    async fn add_subtitle(&mut self, _filename: &str, _base: &str, _ext: &str) -> Result<()> {
+    async fn add_subtitle(&mut self, filename: &str, base: &str, _ext: &str) -> Result<()> {
         if self.item_type == ItemType::TVShow {
             return Ok(());
         }
-        // XXX TODO
+
+        // <basename>.srt, or <basename>.<flags>.srt where flags are
+        // a language and/or "forced" and "sdh", e.g. <basename>.en.sdh.srt
+        let flags = match base.strip_prefix(self.basename.as_str()) {
+            Some(rest) if rest == "" || rest.starts_with('.') => rest,
+            _ => return Ok(()),
+        };
+        let mut language = None;
+        let mut sdh = false;
+        let mut forced = false;
+        for flag in flags.split('.').filter(|f| !f.is_empty()) {
+            let flag = flag.to_lowercase();
+            match flag.as_str() {
+                "forced" => forced = true,
+                "sdh" | "cc" => sdh = true,
+                _ if language.is_none() && is_language(&flag) => language = Some(flag),
+                _ => {},
+            }
+        }
+
+        models::Subtitle::add(
+            &mut self.item.subtitles,
+            &self.basedir,
+            filename,
+            self.item.id,
+            language,
+            sdh,
+            forced,
+        )
+        .await?;
+
         Ok(())
     }
 
@@ -206,16 +265,22 @@ impl MediaData {
             }
         }
 
-        // Thumbs in state 'new' or 'deleted': need to update the db.
+        // Thumbs in state 'new', 'updated' or 'deleted': need to update the db.
         if self.item.thumbs.iter().any(|t| t.state != ThumbState::Unchanged) {
             self.updated = true;
         }
         // remove deleted thumbs from the list.
         self.item.thumbs.retain(|t| t.state != ThumbState::Deleted);
 
+        // Same for subtitles.
+        if self.item.subtitles.iter().any(|s| s.state != ThumbState::Unchanged) {
+            self.updated = true;
+        }
+        self.item.subtitles.retain(|s| s.state != ThumbState::Deleted);
+
         // update the type.
         self.item.type_ = match self.item_type {
-            ItemType::Movie => "movies",
+            ItemType::Movie => "movie",
             ItemType::TVShow => "tvshow",
             ItemType::Episode => "episode",
         }.to_string();
@@ -223,3 +288,37 @@ impl MediaData {
         self.updated
     }
 }
+
+// Language code like "en", "dut" or "pt-br".
+fn is_language(s: &str) -> bool {
+    let lang = s.split_once('-').map(|(l, _)| l).unwrap_or(s);
+    (2..=3).contains(&lang.len()) && lang.bytes().all(|b| b.is_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn related_files() {
+        let base = "Show.s01e01";
+        for name in [
+            "Show.s01e01.mp4",
+            "Show.s01e01.nfo",
+            "Show.s01e01-thumb.jpg",
+            "Show.s01e01.en.sdh.srt",
+        ] {
+            assert!(is_related_file(base, name), "{}", name);
+        }
+        for name in [
+            "Show.s01e01-e02.mp4",
+            "Show.s01e01-e02.nfo",
+            "Show.s01e01-e02-thumb.jpg",
+            "Show.s01e01e02.en.srt",
+            "Show.s01e01.e02.mp4",
+            "Show.s01e010.mp4",
+        ] {
+            assert!(!is_related_file(base, name), "{}", name);
+        }
+    }
+}
@@ -19,7 +19,7 @@ pub async fn scan_tvshow_dir(
     let dirinfo = FileInfo::from_path(&coll.directory, dirname).await.ok()?;
     let dirpath = dirinfo.fullpath.clone();
     let mut entries = Vec::new();
-    let (oldest, newest) = scandirs::read_dir(&dirpath, false, &mut entries, true).await.ok()?;
+    let (oldest, newest) = scandirs::read_dir(&dirpath, true, &mut entries, true).await.ok()?;
 
     // Initial TVShow.
     let mut tvshow = dbent.unwrap_or_else(|| {
@@ -2,15 +2,23 @@
 extern crate anyhow;
 
 pub mod api;
+pub(crate) mod blurhash;
 pub mod collections;
 pub mod config;
 pub mod db;
+pub mod dump;
+pub mod events;
 pub mod genres;
 pub(crate) mod id;
 pub mod jvec;
 pub mod kodifs;
 pub mod media;
+pub(crate) mod migrations;
 pub mod models;
+pub mod ratelimit;
+pub mod scanner;
 pub mod server;
+pub mod signing;
 pub mod sqlx;
 pub mod util;
+pub mod watcher;
@@ -4,7 +4,9 @@ use structopt::StructOpt;
 use notflix_backend::collections;
 use notflix_backend::config;
 use notflix_backend::db;
+use notflix_backend::dump;
 use notflix_backend::kodifs;
+use notflix_backend::models;
 use notflix_backend::server;
 
 #[derive(StructOpt, Debug)]
@@ -36,6 +38,14 @@ pub enum Command {
     /// Dump database
     DumpDb(DumpDbOpts),
 
+    #[structopt(display_order = 3)]
+    /// Import database dump (NDJSON)
+    ImportDb(ImportDbOpts),
+
+    #[structopt(display_order = 3)]
+    /// Create an admin user, or make an existing user admin.
+    Admin(AdminOpts),
+
     #[structopt(display_order = 4)]
     /// Read NFO
     ReadNfo(ReadNfoOpts),
@@ -90,10 +100,52 @@ pub struct UpdateOpts {
 
 #[derive(StructOpt, Debug)]
 pub struct DumpDbOpts {
+    #[structopt(long)]
+    /// Output newline-delimited JSON.
+    pub ndjson: bool,
+
+    #[structopt(long)]
+    /// Include deleted items.
+    pub deleted: bool,
+
+    #[structopt(long)]
+    /// Only dump this collection.
+    pub collection: Option<u32>,
+
+    #[structopt(long = "type", possible_values = &["movie", "tvshow", "episode"])]
+    /// Only dump items of this type.
+    pub type_: Option<String>,
+
     /// Database name.
     pub database: String,
 }
 
+#[derive(StructOpt, Debug)]
+pub struct ImportDbOpts {
+    #[structopt(long)]
+    /// Set the password of all imported users.
+    pub password: Option<String>,
+
+    /// Database name. Must be a new, empty database.
+    pub database: String,
+
+    /// Dump file, in NDJSON format (default stdin).
+    pub filename: Option<String>,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct AdminOpts {
+    #[structopt(long)]
+    /// Set the password. Required if the user does not exist yet.
+    pub password: Option<String>,
+
+    /// Database name.
+    pub database: String,
+
+    /// User name.
+    pub username: String,
+}
+
 #[derive(StructOpt, Debug)]
 pub struct ReadNfoOpts {
     /// NFO name.
@@ -111,6 +163,8 @@ async fn main() -> anyhow::Result<()> {
         Command::ScanDir(opts) => return scandir(opts).await,
         Command::Update(opts) => return update(opts).await,
         Command::DumpDb(opts) => return dumpdb(opts).await,
+        Command::ImportDb(opts) => return importdb(opts).await,
+        Command::Admin(opts) => return admin(opts).await,
         Command::ReadNfo(opts) => return readnfo(opts).await,
     }
 }
@@ -123,13 +177,64 @@ async fn serve(opts: ServeOpts) -> anyhow::Result<()> {
     Ok(())
 }
 
-async fn dumpdb(_opts: DumpDbOpts) -> anyhow::Result<()> {
-    /*
-    let handle = db::connect_db(&opts.database).await?;
-    let items = db::get_items(&handle).await?;
-    println!("{}", serde_json::to_string_pretty(&items)?);
-    */
-    eprintln!("not implemented");
+async fn dumpdb(opts: DumpDbOpts) -> anyhow::Result<()> {
+    let db = db::Db::open(&opts.database).await?;
+    let filter = dump::DumpFilter {
+        collection_id: opts.collection,
+        type_: opts.type_,
+        deleted_too: opts.deleted,
+    };
+    dump::dump(&db, &filter, opts.ndjson, std::io::stdout().lock()).await?;
+    Ok(())
+}
+
+async fn importdb(opts: ImportDbOpts) -> anyhow::Result<()> {
+    let db = db::Db::connect(&opts.database).await?;
+    let password = opts.password.as_deref();
+    let counts = match opts.filename.as_deref() {
+        None | Some("-") => dump::import(&db, std::io::stdin().lock(), password).await?,
+        Some(filename) => {
+            let file = std::fs::File::open(filename)?;
+            dump::import(&db, std::io::BufReader::new(file), password).await?
+        },
+    };
+    println!("{:?}", counts);
+    if password.is_none() && counts.users > 0 {
+        eprintln!("note: passwords are not part of the dump, users must get a new password");
+    }
+    Ok(())
+}
+
+async fn admin(opts: AdminOpts) -> anyhow::Result<()> {
+    let db = db::Db::connect(&opts.database).await?;
+    let mut txn = db.handle.begin().await?;
+    match models::User::lookup(&mut txn, &opts.username).await? {
+        Some(user) => {
+            let update = models::UpdateUser {
+                id: user.id,
+                password: opts.password,
+                admin: Some(true),
+                ..models::UpdateUser::default()
+            };
+            update.update(&mut txn).await?;
+            println!("user {} is now an admin", opts.username);
+        },
+        None => {
+            let password = match opts.password {
+                Some(password) => password,
+                None => anyhow::bail!("user {} does not exist, need --password", opts.username),
+            };
+            let mut user = models::User {
+                username: opts.username.clone(),
+                password,
+                admin: true,
+                ..models::User::default()
+            };
+            let id = user.insert(&mut txn).await?;
+            println!("created admin user {} (id {})", opts.username, id);
+        },
+    }
+    txn.commit().await?;
     Ok(())
 }
 
@@ -189,8 +294,11 @@ async fn update(opts: UpdateOpts) -> anyhow::Result<()> {
     }
 
     if opts.movies || opts.tvshows {
-        db.update_collection(&coll).await?;
-        println!("collection {} updated!", opts.directory);
+        let counts = db.update_collection(&coll).await?;
+        println!(
+            "collection {} updated! ({} added, {} updated, {} deleted)",
+            opts.directory, counts.added, counts.updated, counts.deleted
+        );
     }
 
     Ok(())
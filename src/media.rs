@@ -1,21 +1,30 @@
 use std::io;
+use std::path::Component;
 
+use percent_encoding::percent_decode_str;
 use poem::{
     error::Error,
     get, handler,
     http::{Request as HttpRequest, StatusCode},
-    web::headers::{HeaderMapExt, UserAgent},
-    web::{Data, Path},
+    web::headers::{Cookie, HeaderMapExt, UserAgent},
+    web::{Data, Path, Query},
     Request, Response, Result, Route,
 };
+use serde::Deserialize;
 
 use mp4lib::streaming::http_handler::{self, FsPath};
 
-use crate::server::SharedState;
+use crate::server::{find_session, SharedState};
+
+#[derive(Deserialize)]
+struct MediaParams {
+    token: Option<String>,
+}
 
 #[handler]
 async fn handle_request(
     Path((coll_id, path)): Path<(u32, String)>,
+    Query(params): Query<MediaParams>,
     Data(state): Data<&SharedState>,
     req: &Request,
 ) -> Result<Response> {
@@ -25,6 +34,20 @@ async fn handle_request(
         None => return Err(Error::from_status(StatusCode::NOT_FOUND)),
     };
 
+    // Never serve anything outside of the collection directory.
+    let decoded = match decode_path(&path) {
+        Some(decoded) => decoded,
+        None => {
+            log::info!("media: rejecting path {:?} in collection {}", path, coll_id);
+            return Err(Error::from_status(StatusCode::BAD_REQUEST));
+        },
+    };
+
+    // Need a session, or a token for this path.
+    if !authorized(state, req, coll_id, &decoded, params.token.as_deref()).await {
+        return Err(Error::from_status(StatusCode::UNAUTHORIZED));
+    }
+
     // Handle request.
     let req = poem_req_to_http_req(req);
     handle_request2(&path, &coll.directory, &req).await.map_err(|e| translate_io_error(e))
@@ -58,6 +81,50 @@ async fn handle_request2(path: &str, dir: &str, req: &HttpRequest<()>) -> io::Re
     Ok(response.into())
 }
 
+// A valid session in the x-session-id cookie or header, or a valid token.
+async fn authorized(
+    state: &SharedState,
+    req: &Request,
+    coll_id: u32,
+    path: &str,
+    token: Option<&str>,
+) -> bool {
+    if let Some(cookie) = req.headers().typed_get::<Cookie>() {
+        let sessionid = cookie.get("x-session-id").unwrap_or_default();
+        if find_session(state, sessionid).await.is_some() {
+            return true;
+        }
+    }
+    if let Some(sessionid) = req.header("x-session-id") {
+        if find_session(state, sessionid).await.is_some() {
+            return true;
+        }
+    }
+    match token {
+        Some(token) => {
+            let ip = req.remote_addr().as_socket_addr().map(|addr| addr.ip());
+            state.signer.verify(token, coll_id, path, ip).is_some()
+        },
+        None => false,
+    }
+}
+
+/// Percent-decode a path relative to a collection directory.
+///
+/// Returns `None` if the path is not safe, see `safe_path`.
+pub fn decode_path(path: &str) -> Option<String> {
+    let path = percent_decode_str(path).decode_utf8().ok()?;
+    safe_path(&path).then(|| path.into_owned())
+}
+
+/// Check that a path stays inside the collection directory:
+/// it must not be absolute, or have `.` or `..` in it.
+pub fn safe_path(path: &str) -> bool {
+    !path.is_empty()
+        && !path.contains('\0')
+        && std::path::Path::new(path).components().all(|c| matches!(c, Component::Normal(_)))
+}
+
 fn translate_io_error(err: io::Error) -> Error {
     use StatusCode as SC;
     let status = match err.kind() {
@@ -91,3 +158,18 @@ fn poem_req_to_http_req(req: &poem::Request) -> poem::http::Request<()> {
 
     http_req.body(()).unwrap()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_path() {
+        assert_eq!(decode_path("Movie%20(2020)/movie.mp4").unwrap(), "Movie (2020)/movie.mp4");
+        assert!(decode_path("Movie/../../etc/passwd").is_none());
+        assert!(decode_path("Movie/%2e%2e/%2E%2E/etc/passwd").is_none());
+        assert!(decode_path("%2Fetc/passwd").is_none());
+        assert!(decode_path("./movie.mp4").is_none());
+        assert!(decode_path("movie.mp4%00.jpg").is_none());
+    }
+}
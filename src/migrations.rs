@@ -0,0 +1,172 @@
+//! Versioned database schema migrations.
+//!
+//! The migrations live in `db/migrations/NNNN_name.sql` and are embedded
+//! in the binary. They are forward-only: when the database is opened we
+//! look at the highest version in the `schema_migrations` table and apply
+//! every newer migration, in order, in one transaction.
+//!
+use anyhow::{Context, Result};
+use sqlx::Executor;
+
+use crate::db::{DbHandle, TxnHandle};
+use crate::util::Rfc3339Time;
+
+struct Migration {
+    version: i64,
+    name: &'static str,
+    sql: &'static str,
+}
+
+macro_rules! migration {
+    ($version:expr, $name:expr) => {
+        Migration {
+            version: $version,
+            name: $name,
+            sql: include_str!(concat!("../db/migrations/", $name, ".sql")),
+        }
+    };
+}
+
+static MIGRATIONS: &[Migration] = &[
+    migration!(1, "0001_initial"),
+    migration!(2, "0002_mediaitem_sequence"),
+    migration!(3, "0003_seen"),
+    migration!(4, "0004_episode_range"),
+    migration!(5, "0005_image_cache"),
+    migration!(6, "0006_image_format"),
+    migration!(7, "0007_search"),
+    migration!(8, "0008_mediaitem_indexes"),
+    migration!(9, "0009_sync"),
+    migration!(10, "0010_user_admin"),
+    migration!(11, "0011_signing_keys"),
+    migration!(12, "0012_api_tokens"),
+    migration!(13, "0013_session_client"),
+    migration!(14, "0014_audit_log"),
+    migration!(15, "0015_image_original_unique"),
+];
+
+/// The schema version this binary was built for.
+pub fn latest_version() -> i64 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+/// Bring the database schema up to date.
+///
+/// Note that we use `sqlx::query()` instead of `sqlx::query!()` here,
+/// since the tables might not exist yet.
+pub async fn run(dbh: &DbHandle) -> Result<()> {
+    let mut txn = dbh.begin().await?;
+
+    txn.execute(
+        r#"
+            CREATE TABLE IF NOT EXISTS schema_migrations(
+              version INTEGER PRIMARY KEY NOT NULL,
+              name TEXT NOT NULL,
+              applied TEXT NOT NULL
+            )"#,
+    )
+    .await?;
+
+    let mut current = current_version(&mut txn).await?;
+    let latest = latest_version();
+    if current > latest {
+        bail!(
+            "database schema version {} is newer than this binary supports ({})",
+            current,
+            latest
+        );
+    }
+
+    // A database that was created from the old db/schema.sql.
+    if current == 0 && table_exists(&mut txn, "mediaitems").await? {
+        log::info!("migrations: existing database without schema version, assuming version 1");
+        record(&mut txn, &MIGRATIONS[0]).await?;
+        current = 1;
+    }
+
+    for m in MIGRATIONS.iter().filter(|m| m.version > current) {
+        log::info!("migrations: applying {}", m.name);
+        txn.execute(m.sql).await.with_context(|| format!("migration {} failed", m.name))?;
+        record(&mut txn, m).await?;
+    }
+
+    txn.commit().await?;
+    Ok(())
+}
+
+/// Check that the database schema is up to date, without changing it.
+pub async fn check(dbh: &DbHandle) -> Result<()> {
+    let mut txn = dbh.begin().await?;
+    let current = match table_exists(&mut txn, "schema_migrations").await? {
+        true => current_version(&mut txn).await?,
+        false => 0,
+    };
+    let latest = latest_version();
+    if current != latest {
+        bail!("database schema version is {}, expected {}", current, latest);
+    }
+    Ok(())
+}
+
+async fn current_version(txn: &mut TxnHandle<'_>) -> Result<i64> {
+    let row: (Option<i64>,) =
+        sqlx::query_as("SELECT MAX(version) FROM schema_migrations").fetch_one(&mut *txn).await?;
+    Ok(row.0.unwrap_or(0))
+}
+
+async fn table_exists(txn: &mut TxnHandle<'_>, name: &str) -> Result<bool> {
+    let row = sqlx::query("SELECT name FROM sqlite_master WHERE type = 'table' AND name = ?")
+        .bind(name)
+        .fetch_optional(&mut *txn)
+        .await?;
+    Ok(row.is_some())
+}
+
+async fn record(txn: &mut TxnHandle<'_>, m: &Migration) -> Result<()> {
+    let now = Rfc3339Time::new(std::time::SystemTime::now());
+    sqlx::query("INSERT INTO schema_migrations(version, name, applied) VALUES(?, ?, ?)")
+        .bind(m.version)
+        .bind(m.name)
+        .bind(now)
+        .execute(&mut *txn)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePool;
+
+    #[tokio::test]
+    async fn migrate_fresh_database() {
+        let dbh = SqlitePool::connect("sqlite::memory:").await.unwrap();
+        run(&dbh).await.unwrap();
+        // Running it twice is a no-op.
+        run(&dbh).await.unwrap();
+
+        let mut txn = dbh.begin().await.unwrap();
+        assert_eq!(current_version(&mut txn).await.unwrap(), latest_version());
+        assert!(table_exists(&mut txn, "mediaitems").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn check_version() {
+        let dbh = SqlitePool::connect("sqlite::memory:").await.unwrap();
+        assert!(check(&dbh).await.is_err());
+        run(&dbh).await.unwrap();
+        check(&dbh).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn refuse_newer_database() {
+        let dbh = SqlitePool::connect("sqlite::memory:").await.unwrap();
+        run(&dbh).await.unwrap();
+        sqlx::query("INSERT INTO schema_migrations(version, name, applied) VALUES(?, 'x', 'x')")
+            .bind(latest_version() + 1)
+            .execute(&dbh)
+            .await
+            .unwrap();
+        assert!(run(&dbh).await.is_err());
+    }
+}
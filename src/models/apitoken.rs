@@ -0,0 +1,269 @@
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+use poem_openapi::{Enum, Object};
+use rand::Rng;
+use sha2::{Digest, Sha256};
+
+use crate::db;
+use crate::models::Session;
+use crate::util::SystemTimeToUnixTime;
+
+/// What a token can be used for.
+#[derive(Enum, Clone, Copy, Debug, PartialEq)]
+#[oai(rename_all = "snake_case")]
+pub enum TokenScope {
+    /// Only GET requests.
+    Read,
+    /// All requests.
+    Write,
+}
+
+impl TokenScope {
+    fn as_str(&self) -> &'static str {
+        match self {
+            TokenScope::Read => "read",
+            TokenScope::Write => "write",
+        }
+    }
+
+    fn from_db(scope: &str) -> TokenScope {
+        match scope {
+            "write" => TokenScope::Write,
+            _ => TokenScope::Read,
+        }
+    }
+}
+
+/// Personal access token, in the `api_tokens` table.
+#[derive(Object, Clone, Debug)]
+pub struct ApiToken {
+    /// Id
+    pub id: i64,
+    /// User id
+    pub user_id: i64,
+    /// Name, to recognize the token by.
+    pub name: String,
+    /// Scope
+    pub scope: TokenScope,
+    /// Time the token was created (unix timestamp in ms).
+    pub created: i64,
+    /// Time the token expires (unix timestamp in ms). Never if not set.
+    pub expires: Option<i64>,
+    /// Time the token was last used (unix timestamp in ms).
+    pub last_used: Option<i64>,
+}
+
+impl ApiToken {
+    /// Tokens start with this, so that they can be told apart from session ids.
+    pub const PREFIX: &'static str = "nfx_";
+
+    /// Create a new token. Returns the token itself, which is not stored.
+    pub async fn create(
+        txn: &mut db::TxnHandle<'_>,
+        user_id: i64,
+        name: &str,
+        scope: TokenScope,
+        expires: Option<i64>,
+    ) -> Result<(ApiToken, String)> {
+        let random = rand::thread_rng().gen::<[u8; 20]>();
+        let token = random.iter().fold(ApiToken::PREFIX.to_string(), |mut s, b| {
+            s.push_str(&format!("{:02x}", b));
+            s
+        });
+        let hash = hash(&token);
+        let scope_str = scope.as_str();
+        let created = SystemTime::now().unixtime_ms();
+
+        let id = sqlx::query!(
+            r#"
+                INSERT INTO api_tokens(user_id, name, hash, scope, created, expires)
+                VALUES(?, ?, ?, ?, ?, ?)"#,
+            user_id,
+            name,
+            hash,
+            scope_str,
+            created,
+            expires,
+        )
+        .execute(&mut *txn)
+        .await?
+        .last_insert_rowid();
+        log::info!("create_token: created token {} \"{}\" for user_id {}", id, name, user_id);
+
+        let api_token = ApiToken {
+            id,
+            user_id,
+            name: name.to_string(),
+            scope,
+            created,
+            expires,
+            last_used: None,
+        };
+        Ok((api_token, token))
+    }
+
+    /// All tokens of a user, including the expired ones.
+    pub async fn list(txn: &mut db::TxnHandle<'_>, user_id: i64) -> Result<Vec<ApiToken>> {
+        let rows = sqlx::query!(
+            r#"
+                SELECT id AS "id!: i64", user_id, name, scope, created, expires, last_used
+                FROM api_tokens
+                WHERE user_id = ?
+                ORDER BY id"#,
+            user_id
+        )
+        .fetch_all(&mut *txn)
+        .await?;
+
+        let tokens = rows
+            .into_iter()
+            .map(|r| ApiToken {
+                id: r.id,
+                user_id: r.user_id,
+                name: r.name,
+                scope: TokenScope::from_db(&r.scope),
+                created: r.created,
+                expires: r.expires,
+                last_used: r.last_used,
+            })
+            .collect();
+        Ok(tokens)
+    }
+
+    /// Revoke a token. If `user_id` is set, only if it belongs to that user.
+    pub async fn delete(
+        txn: &mut db::TxnHandle<'_>,
+        id: i64,
+        user_id: Option<i64>,
+    ) -> Result<bool> {
+        let r = sqlx::query!(
+            r#"
+                DELETE FROM api_tokens
+                WHERE id = ? AND (? IS NULL OR user_id = ?)"#,
+            id,
+            user_id,
+            user_id,
+        )
+        .execute(&mut *txn)
+        .await?;
+        Ok(r.rows_affected() > 0)
+    }
+
+    /// Check that a token still exists and has not expired.
+    pub async fn is_valid(txn: &mut db::TxnHandle<'_>, id: i64) -> Result<bool> {
+        let row = sqlx::query!(
+            r#"
+                SELECT expires FROM api_tokens WHERE id = ?"#,
+            id
+        )
+        .fetch_optional(&mut *txn)
+        .await?;
+        let now = SystemTime::now().unixtime_ms();
+        Ok(row.map(|r| r.expires.map(|e| e >= now).unwrap_or(true)).unwrap_or(false))
+    }
+
+    /// Find the session of the user that a token belongs to.
+    pub async fn find(txn: &mut db::TxnHandle<'_>, token: &str) -> Result<Option<Session>> {
+        let hash = hash(token);
+        let row = sqlx::query!(
+            r#"
+                SELECT
+                    t.id AS "id!: i64",
+                    t.user_id AS "user_id!: i64",
+                    t.scope,
+                    t.expires,
+                    t.last_used,
+                    u.username,
+                    u.admin AS "admin!: bool"
+                FROM api_tokens t, users u
+                WHERE t.user_id = u.id AND t.hash = ?"#,
+            hash
+        )
+        .fetch_optional(&mut *txn)
+        .await?;
+
+        let t = match row {
+            Some(t) => t,
+            None => {
+                log::debug!("find_token: token not found in db");
+                return Ok(None);
+            },
+        };
+
+        let now = SystemTime::now().unixtime_ms();
+        if t.expires.map(|e| e < now).unwrap_or(false) {
+            log::info!("find_token: token {} for {}: expired", t.id, t.username);
+            return Ok(None);
+        }
+
+        // Like sessions, only record the use every 5 minutes.
+        let every = Duration::from_secs(300).as_millis() as i64;
+        if t.last_used.map(|l| now - l >= every).unwrap_or(true) {
+            sqlx::query!(
+                r#"
+                    UPDATE api_tokens SET last_used = ? WHERE id = ?"#,
+                now,
+                t.id,
+            )
+            .execute(&mut *txn)
+            .await?;
+        }
+
+        Ok(Some(Session {
+            username: t.username,
+            user_id: t.user_id,
+            sessionid: format!("token-{}", t.id),
+            admin: t.admin,
+            token_id: Some(t.id),
+            read_only: TokenScope::from_db(&t.scope) == TokenScope::Read,
+        }))
+    }
+}
+
+fn hash(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn find_token() {
+        let db = db::Db::connect("sqlite::memory:").await.unwrap();
+        let mut txn = db.handle.begin().await.unwrap();
+        sqlx::query("INSERT INTO users(id, username, password) VALUES(5, 'u', '!')")
+            .execute(&mut txn)
+            .await
+            .unwrap();
+
+        let (write, token) =
+            ApiToken::create(&mut txn, 5, "w", TokenScope::Write, None).await.unwrap();
+        let session = ApiToken::find(&mut txn, &token).await.unwrap().unwrap();
+        assert_eq!(
+            (session.user_id, session.token_id, session.read_only),
+            (5, Some(write.id), false)
+        );
+
+        // Only the hash is stored.
+        let row: (String,) = sqlx::query_as("SELECT hash FROM api_tokens WHERE id = ?")
+            .bind(write.id)
+            .fetch_one(&mut txn)
+            .await
+            .unwrap();
+        assert_eq!(row.0, hash(&token));
+        assert!(ApiToken::find(&mut txn, &row.0).await.unwrap().is_none());
+        assert!(ApiToken::find(&mut txn, "nfx_0000").await.unwrap().is_none());
+
+        let (_, token) = ApiToken::create(&mut txn, 5, "r", TokenScope::Read, None).await.unwrap();
+        let session = ApiToken::find(&mut txn, &token).await.unwrap().unwrap();
+        assert!(session.read_only);
+
+        let past = SystemTime::now().unixtime_ms() - 1000;
+        let (expired, token) =
+            ApiToken::create(&mut txn, 5, "e", TokenScope::Write, Some(past)).await.unwrap();
+        assert!(ApiToken::find(&mut txn, &token).await.unwrap().is_none());
+        assert!(!ApiToken::is_valid(&mut txn, expired.id).await.unwrap());
+    }
+}
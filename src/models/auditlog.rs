@@ -0,0 +1,120 @@
+use std::time::SystemTime;
+
+use anyhow::Result;
+use poem_openapi::{Enum, Object};
+
+use crate::db;
+use crate::models::Client;
+use crate::util::SystemTimeToUnixTime;
+
+/// What happened.
+#[derive(Enum, Clone, Copy, Debug, PartialEq)]
+#[oai(rename_all = "snake_case")]
+pub enum AuditEvent {
+    /// Successful login.
+    Login,
+    /// Wrong password or unknown user.
+    LoginFailed,
+}
+
+impl AuditEvent {
+    fn as_str(&self) -> &'static str {
+        match self {
+            AuditEvent::Login => "login",
+            AuditEvent::LoginFailed => "login_failed",
+        }
+    }
+
+    fn from_db(event: &str) -> AuditEvent {
+        match event {
+            "login" => AuditEvent::Login,
+            _ => AuditEvent::LoginFailed,
+        }
+    }
+}
+
+/// Entry in the `audit_log` table.
+#[derive(Object, Clone, Debug)]
+pub struct AuditEntry {
+    /// Id
+    pub id: i64,
+    /// Time (unix timestamp in ms).
+    pub time: i64,
+    /// What happened.
+    pub event: AuditEvent,
+    /// Username, as sent by the client.
+    pub username: String,
+    /// User id, if the user exists.
+    pub user_id: Option<i64>,
+    /// Device name, as sent by the client.
+    pub device: Option<String>,
+    /// User-Agent of the client.
+    pub user_agent: Option<String>,
+    /// IP address of the client.
+    pub remote_addr: Option<String>,
+}
+
+impl AuditEntry {
+    /// Add an entry to the audit log.
+    pub async fn insert(
+        txn: &mut db::TxnHandle<'_>,
+        event: AuditEvent,
+        username: &str,
+        user_id: Option<i64>,
+        client: &Client,
+    ) -> Result<()> {
+        let time = SystemTime::now().unixtime_ms();
+        let event = event.as_str();
+        sqlx::query!(
+            r#"
+                INSERT INTO audit_log(time, event, username, user_id,
+                                      device, user_agent, remote_addr)
+                VALUES(?, ?, ?, ?, ?, ?, ?)"#,
+            time,
+            event,
+            username,
+            user_id,
+            client.device,
+            client.user_agent,
+            client.remote_addr,
+        )
+        .execute(&mut *txn)
+        .await?;
+        Ok(())
+    }
+
+    /// Entries of the audit log, newest first.
+    pub async fn list(
+        txn: &mut db::TxnHandle<'_>,
+        offset: u32,
+        limit: u32,
+    ) -> Result<Vec<AuditEntry>> {
+        let rows = sqlx::query!(
+            r#"
+                SELECT id AS "id!: i64", time, event, username, user_id,
+                       device, user_agent, remote_addr
+                FROM audit_log
+                ORDER BY id DESC
+                LIMIT ? OFFSET ?"#,
+            limit,
+            offset,
+        )
+        .fetch_all(&mut *txn)
+        .await?;
+
+        let entries = rows
+            .into_iter()
+            .map(|r| AuditEntry {
+                id: r.id,
+                time: r.time,
+                event: AuditEvent::from_db(&r.event),
+                username: r.username,
+                user_id: r.user_id,
+                device: r.device,
+                user_agent: r.user_agent,
+                remote_addr: r.remote_addr,
+            })
+            .collect();
+        Ok(entries)
+    }
+}
@@ -0,0 +1,76 @@
+use anyhow::Result;
+use poem_openapi::{Enum, Object};
+use serde::Serialize;
+
+use crate::db;
+
+/// Things to browse a collection by.
+#[derive(Enum, Clone, Copy, Debug, PartialEq)]
+#[oai(rename_all = "lowercase")]
+pub enum FacetType {
+    Genre,
+    Studio,
+    Country,
+    Director,
+    Actor,
+}
+
+impl FacetType {
+    // Path of the list of values in `nfo_info`.
+    fn json_path(&self) -> &'static str {
+        match self {
+            FacetType::Genre => "$.genres",
+            FacetType::Studio => "$.studios",
+            FacetType::Country => "$.countries",
+            FacetType::Director => "$.directors",
+            FacetType::Actor => "$.actors",
+        }
+    }
+}
+
+/// One genre, studio, country, director or actor.
+#[derive(Object, Serialize, Clone, Debug)]
+pub struct Facet {
+    /// Name.
+    pub name: String,
+    /// Number of movies or tvshows.
+    pub count: u64,
+}
+
+impl Facet {
+    /// All values of a facet in a collection, with the number of items, by name.
+    pub async fn list(
+        dbh: &db::DbHandle,
+        collection_id: i64,
+        type_: &str,
+        facet: FacetType,
+    ) -> Result<Vec<Facet>> {
+        // Actors are objects, the rest are strings.
+        let json_path = facet.json_path();
+        let rows = sqlx::query!(
+            r#"
+                SELECT name AS "name!: String",
+                       COUNT(DISTINCT id) AS "count!: i64"
+                FROM (
+                    SELECT i.id,
+                           TRIM(CASE WHEN f.type = 'object'
+                                     THEN json_extract(f.value, '$.name')
+                                     ELSE f.value END) AS name
+                    FROM mediaitems i, json_each(i.nfo_info, ?) f
+                    WHERE i.collection_id = ? AND i.type = ? AND i.deleted = 0
+                )
+                WHERE name IS NOT NULL AND name != ''
+                GROUP BY name COLLATE NOCASE
+                ORDER BY name COLLATE NOCASE"#,
+            json_path,
+            collection_id,
+            type_,
+        )
+        .fetch_all(dbh)
+        .await?;
+
+        let facets =
+            rows.into_iter().map(|r| Facet { name: r.name, count: r.count as u64 }).collect();
+        Ok(facets)
+    }
+}
@@ -1,163 +1,293 @@
-use std::collections::HashMap;
-
 use anyhow::Result;
-use futures_util::TryStreamExt;
-use poem_openapi::Object;
-use serde::{Serialize, Deserialize};
 
-use crate::collections::Collection;
+use super::FileInfo;
 use crate::db;
 use crate::util::Id;
-use super::FileInfo;
 
-/// Image
-#[derive(Serialize, Deserialize, Clone, Debug, Default)]
-#[serde(default)]
+/// Image, in the `images` table.
+///
+/// This is a cache of resized images. Originals have `id == image_id`, and
+/// their `fileinfo` is relative to the directory of the mediaitem. Resized
+/// variants point at the original through `image_id`, their `fileinfo`
+/// is relative to the cache directory.
+#[derive(Clone, Debug, Default)]
 pub struct Image {
     pub id: i64,
-    pub collection_id: i64,
+    pub collection_id: u32,
     pub mediaitem_id: Id,
     pub image_id: i64,
-    pub extra: Option<sqlx::types::Json<HashMap<String, String>>>,
     pub fileinfo: FileInfo,
     pub aspect: String,
     pub width: u32,
     pub height: u32,
     pub quality: u32,
-    #[serde(skip)]
-    pub state: ImageState,
+    pub accessed: i64,
+    /// Image format, as a file extension: "jpg", "png", "webp" etc.
+    pub format: String,
+    /// The image has an alpha channel.
+    pub alpha: bool,
 }
 
-#[derive(Clone, Debug, Default, PartialEq)]
-pub enum ImageState {
-    Deleted,
-    #[default]
-    Unchanged,
-    New,
-}
+impl Image {
+    /// Find the original image of a mediaitem by its path.
+    pub async fn original(
+        dbh: &mut db::TxnHandle<'_>,
+        mediaitem_id: Id,
+        path: &str,
+    ) -> Result<Option<Image>> {
+        let r = sqlx::query_as!(
+            Image,
+            r#"
+                SELECT id AS "id!: i64",
+                       collection_id AS "collection_id!: u32",
+                       mediaitem_id AS "mediaitem_id!: Id",
+                       image_id AS "image_id!: i64",
+                       fileinfo AS "fileinfo!: FileInfo",
+                       aspect,
+                       width AS "width!: u32",
+                       height AS "height!: u32",
+                       quality AS "quality!: u32",
+                       accessed,
+                       format,
+                       alpha AS "alpha!: bool"
+                FROM images
+                WHERE mediaitem_id = ? AND id = image_id
+                  AND json_extract(fileinfo, '$.path') = ?"#,
+            mediaitem_id,
+            path,
+        )
+        .fetch_optional(dbh)
+        .await?;
 
-#[derive(Clone, Debug, Default, Object)]
-pub struct GetImage {
-    #[oai(skip)]
-    pub id: i64,
-    #[oai(skip)]
-    pub image_id: i64,
-    #[oai(skip)]
-    pub fileinfo: FileInfo,
-    pub path: String,
-    pub aspect: String,
-    pub width: u32,
-    pub height: u32,
-    pub quality: u32,
-    pub season: Option<String>,
-}
+        Ok(r)
+    }
 
-impl GetImage {
-    /// Get the thumb and all its currently existing variants.
-    ///
-    /// Can query either by mediaitem_id (gets al images for one mediaitem),
-    /// or specific image_id (gets all variants).
-    pub async fn find(dbh: &mut db::TxnHandle<'_>, coll: &Collection, mediaitem_id: Option<Id>, image_id: Option<i64>, cache_dir: &str) -> Result<Vec<GetImage>> {
+    /// Find a resized variant of an original image.
+    pub async fn variant(
+        dbh: &mut db::TxnHandle<'_>,
+        image_id: i64,
+        width: u32,
+        height: u32,
+        quality: u32,
+        format: &str,
+    ) -> Result<Option<Image>> {
+        let r = sqlx::query_as!(
+            Image,
+            r#"
+                SELECT id AS "id!: i64",
+                       collection_id AS "collection_id!: u32",
+                       mediaitem_id AS "mediaitem_id!: Id",
+                       image_id AS "image_id!: i64",
+                       fileinfo AS "fileinfo!: FileInfo",
+                       aspect,
+                       width AS "width!: u32",
+                       height AS "height!: u32",
+                       quality AS "quality!: u32",
+                       accessed,
+                       format,
+                       alpha AS "alpha!: bool"
+                FROM images
+                WHERE image_id = ? AND id != image_id
+                  AND width = ? AND height = ? AND quality = ? AND format = ?"#,
+            image_id,
+            width,
+            height,
+            quality,
+            format,
+        )
+        .fetch_optional(dbh)
+        .await?;
 
-        let mut rows = sqlx::query!(
+        Ok(r)
+    }
+
+    /// All resized variants of an original image.
+    pub async fn variants(dbh: &mut db::TxnHandle<'_>, image_id: i64) -> Result<Vec<Image>> {
+        let r = sqlx::query_as!(
+            Image,
             r#"
-                SELECT  t.id AS "id!",
-                        t.image_id as "image_id!",
-                        t.fileinfo AS "fileinfo!: FileInfo",
-                        t.aspect AS "aspect!",
-                        t.width AS "width!: u32",
-                        t.height AS "height!: u32",
-                        t.quality AS "quality!: u32",
-                        t.extra AS "extra: sqlx::types::Json<HashMap<String, String>>",
-                        m.directory AS "directory!: FileInfo",
-                        m.id AS "mediaitem_id!: i64"
-                FROM images t JOIN mediaitems m ON t.mediaitem_id = m.id
-                WHERE t.mediaitem_id = ? OR t.image_id = ?
-                ORDER BY t.id != t.image_id"#,
-            mediaitem_id,
+                SELECT id AS "id!: i64",
+                       collection_id AS "collection_id!: u32",
+                       mediaitem_id AS "mediaitem_id!: Id",
+                       image_id AS "image_id!: i64",
+                       fileinfo AS "fileinfo!: FileInfo",
+                       aspect,
+                       width AS "width!: u32",
+                       height AS "height!: u32",
+                       quality AS "quality!: u32",
+                       accessed,
+                       format,
+                       alpha AS "alpha!: bool"
+                FROM images
+                WHERE image_id = ? AND id != image_id"#,
             image_id,
         )
-        .fetch(dbh);
-
-        let mut images = Vec::new();
-        while let Some(mut row) = rows.try_next().await? {
-            let prefix = if row.id == row.image_id {
-                coll.directory.as_str()
-            } else {
-                // sanity check. original _must_ come first.
-                if image_id.is_some() && images.len() == 0 {
-                    return Ok(Vec::new());
-                }
-                cache_dir
-            };
-            row.fileinfo.fullpath = format!("{}/{}/{}", prefix, coll.directory, row.fileinfo.path);
-
-            let ext = match row.fileinfo.path.rsplit_once(".").map(|t| t.1) {
-                Some("tbn") => "jpg",
-                Some(ext) => ext,
-                None => "jpg",
-            };
-            let path = format!("/api/image/{}/{}/{}.{}", coll.collection_id, row.mediaitem_id, row.id, ext);
-            let season = row.extra.and_then(|m| m.get("season").map(|s| s.to_string()));
-
-            images.push(GetImage {
-                id: row.id,
-                image_id: row.image_id,
-                fileinfo: row.fileinfo,
-                path,
-                aspect: row.aspect,
-                width: row.width,
-                height: row.height,
-                quality: row.quality,
-                season,
-            });
-        }
+        .fetch_all(dbh)
+        .await?;
 
-        Ok(images)
+        Ok(r)
     }
 
-    pub async fn delete(&self, txn: &mut db::TxnHandle<'_>) -> Result<()> {
-        let image = Image {
-            id: self.id,
-            image_id: self.image_id,
-            ..Image::default()
-        };
-        image.delete(txn).await
+    /// Resized variants, least recently used first.
+    pub async fn least_recently_used(dbh: &mut db::TxnHandle<'_>) -> Result<Vec<Image>> {
+        let r = sqlx::query_as!(
+            Image,
+            r#"
+                SELECT id AS "id!: i64",
+                       collection_id AS "collection_id!: u32",
+                       mediaitem_id AS "mediaitem_id!: Id",
+                       image_id AS "image_id!: i64",
+                       fileinfo AS "fileinfo!: FileInfo",
+                       aspect,
+                       width AS "width!: u32",
+                       height AS "height!: u32",
+                       quality AS "quality!: u32",
+                       accessed,
+                       format,
+                       alpha AS "alpha!: bool"
+                FROM images
+                WHERE id != image_id
+                ORDER BY accessed"#,
+        )
+        .fetch_all(dbh)
+        .await?;
+
+        Ok(r)
     }
-}
 
-impl Image {
+    /// Total size of all resized variants, in bytes.
+    pub async fn cache_size(dbh: &mut db::TxnHandle<'_>) -> Result<u64> {
+        let r = sqlx::query!(
+            r#"
+                SELECT COALESCE(SUM(json_extract(fileinfo, '$.size')), 0) AS "size!: i64"
+                FROM images
+                WHERE id != image_id"#,
+        )
+        .fetch_one(dbh)
+        .await?;
+
+        Ok(r.size as u64)
+    }
+
+    /// Insert an original (if `image_id` is 0) or a variant.
+    ///
+    /// Sets `self.id`, and for an original, `self.image_id` as well. If the
+    /// original is already in the database, `self` is set to that one.
     pub async fn insert(&mut self, txn: &mut db::TxnHandle<'_>) -> Result<()> {
+        if self.image_id == 0 {
+            // Originals refer to themselves, but the id is not known until
+            // the row has been inserted.
+            sqlx::query("PRAGMA defer_foreign_keys = ON").execute(&mut *txn).await?;
+            let row = sqlx::query!(
+                r#"
+                    INSERT INTO images(
+                        image_id,
+                        collection_id,
+                        mediaitem_id,
+                        fileinfo,
+                        aspect,
+                        width,
+                        height,
+                        quality,
+                        accessed,
+                        format,
+                        alpha
+                    ) VALUES(0, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                    ON CONFLICT DO NOTHING
+                    RETURNING id AS "id!: i64""#,
+                self.collection_id,
+                self.mediaitem_id,
+                self.fileinfo,
+                self.aspect,
+                self.width,
+                self.height,
+                self.quality,
+                self.accessed,
+                self.format,
+                self.alpha,
+            )
+            .fetch_optional(&mut *txn)
+            .await?;
+
+            match row {
+                Some(row) => {
+                    sqlx::query!(
+                        r#"
+                            UPDATE images
+                            SET image_id = id
+                            WHERE id = ?"#,
+                        row.id,
+                    )
+                    .execute(&mut *txn)
+                    .await?;
+                    self.id = row.id;
+                    self.image_id = row.id;
+                },
+                None => {
+                    *self = Image::original(&mut *txn, self.mediaitem_id, &self.fileinfo.path)
+                        .await?
+                        .ok_or_else(|| anyhow!("image: original disappeared"))?;
+                },
+            }
+            return Ok(());
+        }
+
         self.id = sqlx::query!(
             r#"
                 INSERT INTO images(
+                    image_id,
                     collection_id,
                     mediaitem_id,
-                    image_id,
-                    extra,
                     fileinfo,
                     aspect,
                     width,
                     height,
-                    quality
-                ) VALUES(?, ?, ?, ?, ?, ?, ?, ?, ?)"#,
+                    quality,
+                    accessed,
+                    format,
+                    alpha
+                ) VALUES(?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                ON CONFLICT(image_id, width, height, quality, format) DO UPDATE SET
+                    fileinfo = excluded.fileinfo,
+                    accessed = excluded.accessed
+                RETURNING id AS "id!: i64""#,
+            self.image_id,
             self.collection_id,
             self.mediaitem_id,
-            self.image_id,
-            self.extra,
             self.fileinfo,
             self.aspect,
             self.width,
             self.height,
-            self.quality
+            self.quality,
+            self.accessed,
+            self.format,
+            self.alpha,
         )
-        .execute(&mut *txn)
+        .fetch_one(&mut *txn)
         .await?
-        .last_insert_rowid();
+        .id;
 
         Ok(())
     }
 
+    /// Update the time this image was last used.
+    pub async fn touch(&mut self, txn: &mut db::TxnHandle<'_>, accessed: i64) -> Result<()> {
+        self.accessed = accessed;
+        sqlx::query!(
+            r#"
+                UPDATE images
+                SET accessed = ?
+                WHERE id = ?"#,
+            self.accessed,
+            self.id,
+        )
+        .execute(&mut *txn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Delete an image. If this is an original, the variants are deleted as well.
     pub async fn delete(&self, txn: &mut db::TxnHandle<'_>) -> Result<()> {
         if self.id == self.image_id {
             sqlx::query!(
@@ -180,3 +310,39 @@ impl Image {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::MediaItem;
+
+    #[tokio::test]
+    async fn insert_original_once() {
+        let db = db::Db::connect("sqlite::memory:").await.unwrap();
+        let mut txn = db.handle.begin().await.unwrap();
+        let item = MediaItem {
+            type_: "movie".to_string(),
+            id: Id::from_str("m1").unwrap(),
+            ..MediaItem::default()
+        };
+        item.insert(&mut txn).await.unwrap();
+
+        let image = Image {
+            mediaitem_id: item.id,
+            fileinfo: FileInfo {
+                path: "poster.jpg".to_string(),
+                ..FileInfo::default()
+            },
+            ..Image::default()
+        };
+        let mut first = image.clone();
+        first.insert(&mut txn).await.unwrap();
+        assert!(first.id > 0);
+        assert_eq!(first.image_id, first.id);
+
+        let mut second = image.clone();
+        second.insert(&mut txn).await.unwrap();
+        assert_eq!(second.id, first.id);
+        txn.commit().await.unwrap();
+    }
+}
@@ -1,9 +1,10 @@
 use crate::db;
 use crate::jvec::JVec;
-use crate::models::{FileInfo, Thumb};
+use crate::models::{FacetType, FileInfo, Subtitle, Thumb};
 use crate::util::{some_or_return, Id};
 use anyhow::Result;
 use futures_util::TryStreamExt;
+use poem_openapi::Enum;
 
 #[derive(Clone, Debug, sqlx::FromRow)]
 pub struct MediaInfoOverview {
@@ -15,32 +16,213 @@ pub struct MediaInfoOverview {
     pub poster: Option<Thumb>,
 }
 
+/// Sort order of a collection listing.
+#[derive(Enum, Clone, Copy, Debug, Default, PartialEq)]
+#[oai(rename_all = "lowercase")]
+pub enum SortBy {
+    #[default]
+    Title,
+    Sorttitle,
+    Dateadded,
+    Year,
+    Rating,
+    Lastmodified,
+}
+
+impl SortBy {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SortBy::Title => "title",
+            SortBy::Sorttitle => "sorttitle",
+            SortBy::Dateadded => "dateadded",
+            SortBy::Year => "year",
+            SortBy::Rating => "rating",
+            SortBy::Lastmodified => "lastmodified",
+        }
+    }
+}
+
+/// Filters, sort order and pagination of a collection listing.
+#[derive(Default, Debug)]
+pub struct ListFilter {
+    pub genre: Option<String>,
+    pub year_from: Option<u32>,
+    pub year_to: Option<u32>,
+    pub mpaa: Option<String>,
+    pub country: Option<String>,
+    pub studio: Option<String>,
+    pub director: Option<String>,
+    pub actor: Option<String>,
+    /// Minimum rating, on a scale of 0-10.
+    pub rating: Option<f64>,
+    /// Minimum resolution of the video (720, 1080, 2160). Wide videos count
+    /// as if they were 16:9, so 1920x800 is 1080.
+    pub resolution: Option<u32>,
+    /// Watched (or not) by the user. A tvshow is watched when all episodes are.
+    pub watched: Option<bool>,
+    pub sort: SortBy,
+    pub desc: bool,
+    pub offset: u32,
+    pub limit: Option<u32>,
+}
+
+impl ListFilter {
+    /// Only list items with this genre, studio, country, director or actor.
+    pub fn set_facet(&mut self, facet: FacetType, name: String) {
+        match facet {
+            FacetType::Genre => self.genre = Some(name),
+            FacetType::Studio => self.studio = Some(name),
+            FacetType::Country => self.country = Some(name),
+            FacetType::Director => self.director = Some(name),
+            FacetType::Actor => self.actor = Some(name),
+        }
+    }
+}
+
 impl MediaInfoOverview {
-    pub async fn get(
+    /// List the movies or tvshows of a collection.
+    ///
+    /// Returns one page of items, and the total number of items that matched.
+    pub async fn list(
         dbh: &db::DbHandle,
         collection_id: i64,
         type_: &str,
-    ) -> Result<Vec<MediaInfoOverview>> {
-        let mut rows = sqlx::query!(
-            r#"
-                SELECT i.id AS "id!: Id",
-                       i.title,
-                       i.thumbs AS "thumbs!: JVec<Thumb>"
-                FROM mediaitems i
-                WHERE i.collection_id = ? AND i.type = ? AND i.deleted = 0
-                ORDER BY LOWER( title)"#,
-            collection_id,
-            type_,
-        )
-        .fetch(dbh);
+        user_id: i64,
+        filter: &ListFilter,
+    ) -> Result<(Vec<MediaInfoOverview>, u64)> {
+        // This query is too complex for the sqlx::query! macro, it
+        // takes forever to compile. So do it at runtime instead.
+        let with = r#"
+                WITH items AS (
+                    SELECT i.id,
+                           i.title,
+                           i.thumbs,
+                           i.year,
+                           i.dateadded,
+                           i.lastmodified,
+                           COALESCE(json_extract(i.nfo_info, '$.sorttitle'), i.title) AS sorttitle,
+                           (SELECT json_extract(r.value, '$.value') * 10.0 /
+                                   COALESCE(json_extract(r.value, '$.max'), 10)
+                            FROM json_each(i.nfo_info, '$.ratings') r
+                            ORDER BY COALESCE(json_extract(r.value, '$.default'), 0) DESC
+                            LIMIT 1) AS rating,
+                           CASE WHEN i.type = 'tvshow' THEN
+                               (SELECT MAX(MAX(
+                                   COALESCE(json_extract(e.video_info, '$.video_track.height'), 0),
+                                   COALESCE(json_extract(e.video_info, '$.video_track.width'), 0) * 9 / 16))
+                                FROM mediaitems e
+                                WHERE e.tvshow_id = i.id AND e.deleted = 0)
+                           ELSE
+                               MAX(COALESCE(json_extract(i.video_info, '$.video_track.height'), 0),
+                                   COALESCE(json_extract(i.video_info, '$.video_track.width'), 0) * 9 / 16)
+                           END AS resolution,
+                           CASE WHEN i.type = 'tvshow' THEN
+                               EXISTS (SELECT 1 FROM mediaitems e
+                                       WHERE e.tvshow_id = i.id AND e.deleted = 0)
+                               AND NOT EXISTS (
+                                   SELECT 1 FROM mediaitems e
+                                   LEFT JOIN seen s ON s.mediaitem_id = e.id AND s.user_id = ?3
+                                   WHERE e.tvshow_id = i.id AND e.deleted = 0
+                                     AND COALESCE(s.completed, 0) = 0)
+                           ELSE
+                               EXISTS (SELECT 1 FROM seen s
+                                       WHERE s.mediaitem_id = i.id AND s.user_id = ?3
+                                         AND s.completed = 1)
+                           END AS watched
+                    FROM mediaitems i
+                    WHERE i.collection_id = ?1 AND i.type = ?2 AND i.deleted = 0
+                      AND (?4 IS NULL OR EXISTS (
+                          SELECT 1 FROM json_each(i.nfo_info, '$.genres')
+                          WHERE TRIM(value) = TRIM(?4) COLLATE NOCASE))
+                      AND (?5 IS NULL OR i.year >= ?5)
+                      AND (?6 IS NULL OR i.year <= ?6)
+                      AND (?7 IS NULL OR json_extract(i.nfo_info, '$.mpaa') = ?7 COLLATE NOCASE)
+                      AND (?8 IS NULL OR EXISTS (
+                          SELECT 1 FROM json_each(i.nfo_info, '$.countries')
+                          WHERE TRIM(value) = TRIM(?8) COLLATE NOCASE))
+                      AND (?9 IS NULL OR EXISTS (
+                          SELECT 1 FROM json_each(i.nfo_info, '$.studios')
+                          WHERE TRIM(value) = TRIM(?9) COLLATE NOCASE))
+                      AND (?17 IS NULL OR EXISTS (
+                          SELECT 1 FROM json_each(i.nfo_info, '$.directors')
+                          WHERE TRIM(value) = TRIM(?17) COLLATE NOCASE))
+                      AND (?18 IS NULL OR EXISTS (
+                          SELECT 1 FROM json_each(i.nfo_info, '$.actors')
+                          WHERE TRIM(json_extract(value, '$.name')) = TRIM(?18) COLLATE NOCASE))
+                ),
+                filtered AS (
+                    SELECT *,
+                           CASE ?13
+                               WHEN 'title' THEN LOWER(title)
+                               WHEN 'sorttitle' THEN LOWER(sorttitle)
+                               WHEN 'dateadded' THEN dateadded
+                               WHEN 'year' THEN year
+                               WHEN 'rating' THEN rating
+                               WHEN 'lastmodified' THEN lastmodified
+                           END AS sortkey
+                    FROM items
+                    WHERE (?10 IS NULL OR rating >= ?10)
+                      AND (?11 IS NULL OR resolution >= ?11)
+                      AND (?12 IS NULL OR watched = ?12)
+                )"#;
+        let sql = format!(
+            r#"{}
+                SELECT id, title, thumbs, COUNT(*) OVER () AS total
+                FROM filtered
+                ORDER BY sortkey IS NULL,
+                         CASE WHEN ?14 THEN NULL ELSE sortkey END ASC,
+                         CASE WHEN ?14 THEN sortkey END DESC,
+                         LOWER(title),
+                         id
+                LIMIT COALESCE(?15, -1) OFFSET ?16"#,
+            with
+        );
+
+        // Both queries take the same parameters.
+        macro_rules! bind_params {
+            ($query:expr) => {
+                $query
+                    .bind(collection_id)
+                    .bind(type_)
+                    .bind(user_id)
+                    .bind(&filter.genre)
+                    .bind(filter.year_from)
+                    .bind(filter.year_to)
+                    .bind(&filter.mpaa)
+                    .bind(&filter.country)
+                    .bind(&filter.studio)
+                    .bind(filter.rating)
+                    .bind(filter.resolution)
+                    .bind(filter.watched)
+                    .bind(filter.sort.as_str())
+                    .bind(filter.desc)
+                    .bind(filter.limit)
+                    .bind(filter.offset)
+                    .bind(&filter.director)
+                    .bind(&filter.actor)
+            };
+        }
+
+        let query = sqlx::query_as::<_, (Id, String, JVec<Thumb>, i64)>(&sql);
+        let mut rows = bind_params!(query).fetch(dbh);
 
         let mut items = Vec::new();
-        while let Some(row) = rows.try_next().await? {
-            let poster = row.thumbs.0.iter().find(|t| t.aspect == "poster").cloned();
-            items.push(MediaInfoOverview { id: row.id, title: row.title, poster });
+        let mut total = 0;
+        while let Some((id, title, thumbs, count)) = rows.try_next().await? {
+            total = count as u64;
+            let poster = thumbs.0.into_iter().find(|t| t.aspect == "poster");
+            items.push(MediaInfoOverview { id, title, poster });
+        }
+        drop(rows);
+
+        // Past the last page there is no row to get the total from.
+        if items.is_empty() && filter.offset > 0 {
+            let sql = format!("{}\n                SELECT COUNT(*) FROM filtered", with);
+            let query = sqlx::query_as::<_, (i64,)>(&sql);
+            total = bind_params!(query).fetch_one(dbh).await?.0 as u64;
         }
 
-        Ok(items)
+        Ok((items, total))
     }
 }
 
@@ -48,11 +230,15 @@ impl MediaInfoOverview {
 pub struct MediaInfo {
     /// TVShow or Movie id
     pub id: Id,
+    /// Collection id
+    pub collection_id: u32,
     /// Title.
     pub title: String,
     /// Thumbnail in poster aspect (if available)
     pub thumbs: JVec<Thumb>,
-    /// Directory.
+    /// External subtitles.
+    pub subtitles: JVec<Subtitle>,
+    /// Directory. For episodes, this is the directory of the tvshow.
     pub directory: FileInfo,
 }
 
@@ -60,12 +246,15 @@ impl MediaInfo {
     pub async fn get(dbh: &db::DbHandle, id: Id) -> Result<Option<MediaInfo>> {
         let row = sqlx::query!(
             r#"
-                SELECT  id AS "id!: Id",
-                        title,
-                        thumbs AS "thumbs!: JVec<Thumb>",
-                        directory AS "directory!: FileInfo"
-                FROM mediaitems
-                WHERE id = ?"#,
+                SELECT  i.id AS "id!: Id",
+                        i.collection_id AS "collection_id!: u32",
+                        i.title,
+                        i.thumbs AS "thumbs!: JVec<Thumb>",
+                        i.subtitles AS "subtitles!: JVec<Subtitle>",
+                        COALESCE(i.directory, t.directory) AS "directory!: FileInfo"
+                FROM mediaitems i
+                LEFT JOIN mediaitems t ON t.id = i.tvshow_id
+                WHERE i.id = ?"#,
             id
         )
         .fetch_optional(dbh)
@@ -74,9 +263,134 @@ impl MediaInfo {
         let m = some_or_return!(row, Ok(None));
         Ok(Some(MediaInfo {
             id: m.id,
+            collection_id: m.collection_id,
             title: m.title,
             thumbs: m.thumbs,
+            subtitles: m.subtitles,
             directory: m.directory,
         }))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::MediaItem;
+
+    async fn seed(db: &db::Db) {
+        let mut txn = db.handle.begin().await.unwrap();
+        sqlx::query("INSERT INTO users(id, username, password) VALUES(1, 'u', '!')")
+            .execute(&mut txn)
+            .await
+            .unwrap();
+        let movies = [
+            ("a", "Alpha", 2001, "Drama", 1920, 800),
+            ("b", "Bravo", 2010, "Comedy", 1280, 720),
+            ("c", "Charlie", 2020, "Drama", 3840, 2160),
+        ];
+        for (id, title, year, genre, width, height) in movies {
+            let item = MediaItem {
+                type_: "movie".to_string(),
+                id: Id::from_str(id).unwrap(),
+                collection_id: 1,
+                title: title.to_string(),
+                year: Some(year),
+                ..MediaItem::default()
+            };
+            item.insert(&mut txn).await.unwrap();
+            sqlx::query(
+                r#"
+                    UPDATE mediaitems
+                    SET nfo_info = json_object('genres', json_array(?)),
+                        video_info = json_object('video_track',
+                            json_object('width', ?, 'height', ?))
+                    WHERE id = ?"#,
+            )
+            .bind(genre)
+            .bind(width)
+            .bind(height)
+            .bind(id)
+            .execute(&mut txn)
+            .await
+            .unwrap();
+        }
+        sqlx::query(
+            r#"
+                INSERT INTO seen(user_id, mediaitem_id, position, completed, updated)
+                VALUES(1, 'a', 0, 1, 1000)"#,
+        )
+        .execute(&mut txn)
+        .await
+        .unwrap();
+        txn.commit().await.unwrap();
+    }
+
+    async fn list(db: &db::Db, filter: ListFilter) -> (Vec<String>, u64) {
+        let (items, total) =
+            MediaInfoOverview::list(&db.handle, 1, "movie", 1, &filter).await.unwrap();
+        (items.into_iter().map(|i| i.title).collect(), total)
+    }
+
+    #[tokio::test]
+    async fn list_filter() {
+        let db = db::Db::connect("sqlite::memory:").await.unwrap();
+        seed(&db).await;
+
+        let all = list(&db, ListFilter::default()).await;
+        assert_eq!(all, (vec!["Alpha".into(), "Bravo".into(), "Charlie".into()], 3));
+
+        let genre = ListFilter {
+            genre: Some(" drama".to_string()),
+            ..ListFilter::default()
+        };
+        assert_eq!(list(&db, genre).await.0, ["Alpha", "Charlie"]);
+        let year = ListFilter {
+            year_from: Some(2005),
+            ..ListFilter::default()
+        };
+        assert_eq!(list(&db, year).await.0, ["Bravo", "Charlie"]);
+
+        let watched = ListFilter {
+            watched: Some(true),
+            ..ListFilter::default()
+        };
+        assert_eq!(list(&db, watched).await.0, ["Alpha"]);
+        let unwatched = ListFilter {
+            watched: Some(false),
+            ..ListFilter::default()
+        };
+        assert_eq!(list(&db, unwatched).await.0, ["Bravo", "Charlie"]);
+
+        // 1920x800 counts as 1080.
+        let hd = ListFilter {
+            resolution: Some(1080),
+            ..ListFilter::default()
+        };
+        assert_eq!(list(&db, hd).await.0, ["Alpha", "Charlie"]);
+        let uhd = ListFilter {
+            resolution: Some(2160),
+            ..ListFilter::default()
+        };
+        assert_eq!(list(&db, uhd).await.0, ["Charlie"]);
+
+        let sort = ListFilter {
+            sort: SortBy::Year,
+            desc: true,
+            ..ListFilter::default()
+        };
+        assert_eq!(list(&db, sort).await.0, ["Charlie", "Bravo", "Alpha"]);
+
+        let page = ListFilter {
+            limit: Some(1),
+            offset: 1,
+            ..ListFilter::default()
+        };
+        assert_eq!(list(&db, page).await, (vec!["Bravo".into()], 3));
+        let past_end = ListFilter {
+            limit: Some(1),
+            offset: 5,
+            ..ListFilter::default()
+        };
+        assert_eq!(list(&db, past_end).await, (vec![], 3));
+    }
+}
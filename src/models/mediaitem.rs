@@ -3,7 +3,7 @@ use serde::{Deserialize, Serialize};
 
 use crate::db;
 use crate::jvec::JVec;
-use crate::models::{FileInfo, Nfo, Thumb, Video};
+use crate::models::{FileInfo, Nfo, Subtitle, Thumb, Video};
 use crate::sqlx::impl_sqlx_traits_for;
 use crate::util::Id;
 
@@ -37,7 +37,8 @@ pub struct MediaItem {
 
     /// Thumbs, posters, fanart etc on the filesystem.
     pub thumbs: JVec<Thumb>,
-    // pub subtitles: JVec<Subtitle>,
+    /// External subtitle files.
+    pub subtitles: JVec<Subtitle>,
 
     /// Video file and info.
     pub video_file: Option<FileInfo>,
@@ -47,6 +48,8 @@ pub struct MediaItem {
     pub season: Option<u32>,
     /// Episode specific.
     pub episode: Option<u32>,
+    /// Episode specific: last episode, if the video contains more than one.
+    pub episode_last: Option<u32>,
     /// Episode specific.
     pub tvshow_id: Option<Id>,
 }
@@ -82,10 +85,12 @@ impl MediaItem {
                        nfo_file AS "nfo_file?: FileInfo",
                        nfo_info AS "nfo_info?: Nfo",
                        thumbs AS "thumbs!: JVec<Thumb>",
+                       subtitles AS "subtitles!: JVec<Subtitle>",
                        video_file AS "video_file?: FileInfo",
                        video_info AS "video_info?: Video",
                        season AS "season?: u32",
                        episode AS "episode?: u32",
+                       episode_last AS "episode_last?: u32",
                        tvshow_id AS "tvshow_id?: Id"
                 FROM mediaitems
                 WHERE id = ? AND (deleted = 0 OR deleted = ?)"#,
@@ -98,6 +103,46 @@ impl MediaItem {
         Ok(r.map(|r| Box::new(r)))
     }
 
+    /// Get all episodes of a tvshow, ordered by season and episode.
+    pub async fn get_episodes(
+        dbh: &mut db::TxnHandle<'_>,
+        tvshow_id: Id,
+        deleted_too: bool,
+    ) -> Result<Vec<MediaItem>> {
+        let r = sqlx::query_as!(
+            MediaItem,
+            r#"
+                SELECT id AS "id: Id",
+                       type AS "type_",
+                       collection_id AS "collection_id: u32",
+                       lastmodified,
+                       dateadded,
+                       directory AS "directory?: FileInfo",
+                       deleted AS "deleted!: bool",
+                       title AS "title!: String",
+                       year AS "year?: u32",
+                       nfo_file AS "nfo_file?: FileInfo",
+                       nfo_info AS "nfo_info?: Nfo",
+                       thumbs AS "thumbs!: JVec<Thumb>",
+                       subtitles AS "subtitles!: JVec<Subtitle>",
+                       video_file AS "video_file?: FileInfo",
+                       video_info AS "video_info?: Video",
+                       season AS "season?: u32",
+                       episode AS "episode?: u32",
+                       episode_last AS "episode_last?: u32",
+                       tvshow_id AS "tvshow_id?: Id"
+                FROM mediaitems
+                WHERE tvshow_id = ? AND type = 'episode' AND (deleted = 0 OR deleted = ?)
+                ORDER BY season, episode"#,
+            tvshow_id,
+            deleted_too,
+        )
+        .fetch_all(dbh)
+        .await?;
+
+        Ok(r)
+    }
+
     pub async fn insert(&self, txn: &mut db::TxnHandle<'_>) -> Result<()> {
         sqlx::query!(
             r#"
@@ -114,12 +159,15 @@ impl MediaItem {
                     nfo_file,
                     nfo_info,
                     thumbs,
+                    subtitles,
                     video_file,
                     video_info,
                     season,
                     episode,
+                    episode_last,
                     tvshow_id
-                ) VALUES("movie", ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#,
+                ) VALUES(?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#,
+            self.type_,
             self.id,
             self.collection_id,
             self.lastmodified,
@@ -131,10 +179,12 @@ impl MediaItem {
             self.nfo_file,
             self.nfo_info,
             self.thumbs,
+            self.subtitles,
             self.video_file,
             self.video_info,
             self.season,
             self.episode,
+            self.episode_last,
             self.tvshow_id,
         )
         .execute(&mut *txn)
@@ -152,13 +202,17 @@ impl MediaItem {
                     dateadded = ?,
                     directory = ?,
                     deleted = ?,
+                    title = ?,
+                    year = ?,
                     nfo_file = ?,
                     nfo_info = ?,
                     thumbs = ?,
+                    subtitles = ?,
                     video_file = ?,
                     video_info = ?,
                     season = ?,
                     episode = ?,
+                    episode_last = ?,
                     tvshow_id = ?
                 WHERE id = ?"#,
             self.collection_id,
@@ -166,13 +220,17 @@ impl MediaItem {
             self.dateadded,
             self.directory,
             self.deleted,
+            self.title,
+            self.year,
             self.nfo_file,
             self.nfo_info,
             self.thumbs,
+            self.subtitles,
             self.video_file,
             self.video_info,
             self.season,
             self.episode,
+            self.episode_last,
             self.tvshow_id,
             self.id
         )
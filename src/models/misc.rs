@@ -1,8 +1,10 @@
+use anyhow::Result;
 use poem_openapi::Object;
 use serde::{Deserialize, Serialize};
 
-use super::{is_default, FileInfo, Thumb};
+use super::{is_default, FileInfo, Thumb, ThumbState};
 use crate::sqlx::impl_sqlx_traits_for;
+use crate::util::Id;
 
 #[derive(Object, Deserialize, Serialize, Clone, Default, Debug, PartialEq)]
 #[serde(default)]
@@ -46,6 +48,7 @@ pub struct UniqueId {
 }
 impl_sqlx_traits_for!(UniqueId);
 
+/// External subtitle file.
 #[derive(Object, Deserialize, Serialize, Clone, Default, Debug, PartialEq)]
 #[serde(default)]
 pub struct Subtitle {
@@ -53,8 +56,40 @@ pub struct Subtitle {
     pub language: Option<String>,
     pub sdh: bool,
     pub forced: bool,
-    #[serde(skip)]
+    /// URL of the subtitle, in WebVTT format.
     pub path: String,
     #[oai(skip)]
     pub fileinfo: FileInfo,
+    #[serde(skip)]
+    #[oai(skip)]
+    pub state: ThumbState,
+}
+
+impl Subtitle {
+    pub async fn add(
+        subtitles: &mut Vec<Subtitle>,
+        basedir: &str,
+        path: &str,
+        mediaitem_id: Id,
+        language: Option<String>,
+        sdh: bool,
+        forced: bool,
+    ) -> Result<bool> {
+        let fileinfo = FileInfo::from_path(basedir, path).await?;
+        if let Some(sub) = subtitles.iter_mut().find(|s| s.fileinfo == fileinfo) {
+            sub.state = ThumbState::Unchanged;
+            return Ok(false);
+        }
+        let id = subtitles.iter().fold(0, |acc, s| std::cmp::max(acc, s.id)) + 1;
+        subtitles.push(Subtitle {
+            id,
+            language,
+            sdh,
+            forced,
+            path: format!("/api/subtitle/{}/{}.vtt", mediaitem_id, id),
+            fileinfo,
+            state: ThumbState::New,
+        });
+        Ok(true)
+    }
 }
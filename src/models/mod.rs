@@ -1,35 +1,43 @@
-// mod episode;
+mod apitoken;
+mod auditlog;
+mod facet;
 mod fileinfo;
-// mod image;
+mod image;
 mod mediainfo;
 mod mediaitem;
 mod misc;
-// mod movie;
 mod nfo;
+mod search;
+mod seen;
 mod session;
+mod signingkey;
+mod sync;
 mod thumb;
-// mod tvshow;
 mod uniqueids;
 mod user;
 mod video;
 
-// pub use episode::Episode;
+pub use self::image::Image;
+pub use apitoken::{ApiToken, TokenScope};
+pub use auditlog::{AuditEntry, AuditEvent};
+pub use facet::{Facet, FacetType};
 pub use fileinfo::FileInfo;
-// pub use self::image::{Image, GetImage, ImageState};
-pub use mediainfo::{MediaInfo, MediaInfoOverview};
+pub use mediainfo::{ListFilter, MediaInfo, MediaInfoOverview, SortBy};
 pub use mediaitem::MediaItem;
 pub use misc::*;
-// pub use movie::Movie;
 pub use nfo::Nfo;
-pub use session::Session;
+pub use search::SearchResult;
+pub use seen::{Seen, SeenItem};
+pub use session::{Client, Session, SessionInfo};
+pub use signingkey::SigningKey;
+pub use sync::SyncItem;
 pub use thumb::{Thumb, ThumbState};
-// pub use tvshow::{Season, TVShow};
 pub use uniqueids::UniqueIds;
 pub use user::{UpdateUser, User};
 pub use video::*;
 
 // helper function.
-fn is_default<'a, T>(t: &'a T) -> bool
+pub(crate) fn is_default<'a, T>(t: &'a T) -> bool
 where
     T: Default,
     T: PartialEq,
@@ -0,0 +1,118 @@
+use anyhow::Result;
+use poem_openapi::Object;
+use serde::Serialize;
+
+use crate::db;
+use crate::jvec::JVec;
+use crate::models::Thumb;
+use crate::util::Id;
+
+/// A movie, tvshow or episode that matched a search.
+#[derive(Object, Serialize, Clone, Debug)]
+pub struct SearchResult {
+    /// Movie, tvshow or episode id.
+    #[oai(read_only)]
+    pub id: Id,
+    /// "movie", "tvshow" or "episode".
+    #[oai(rename = "type")]
+    pub type_: String,
+    /// Collection id
+    pub collection_id: u32,
+    /// Title.
+    pub title: String,
+    /// Year.
+    pub year: Option<u32>,
+    /// Episode specific.
+    #[oai(read_only)]
+    pub tvshow_id: Option<Id>,
+    /// Episode specific.
+    pub tvshow_title: Option<String>,
+    /// Episode specific.
+    pub season: Option<u32>,
+    /// Episode specific.
+    pub episode: Option<u32>,
+    /// Poster of the movie or tvshow. For episodes, the poster of the tvshow.
+    pub poster: Option<Thumb>,
+}
+
+impl SearchResult {
+    /// Search titles, plots, actors, directors and studios. Best matches first.
+    ///
+    /// Every word in `query` is a prefix, and all words must match.
+    pub async fn search(
+        dbh: &mut db::TxnHandle<'_>,
+        query: &str,
+        limit: u32,
+    ) -> Result<Vec<SearchResult>> {
+        let query = match fts_query(query) {
+            Some(query) => query,
+            None => return Ok(Vec::new()),
+        };
+
+        // Columns: mediaitem_id, title, originaltitle, plot, tagline, actors, directors, studios.
+        let rows = sqlx::query!(
+            r#"
+                SELECT i.id AS "id!: Id",
+                       i.type AS "type_!: String",
+                       i.collection_id AS "collection_id!: u32",
+                       i.title AS "title!: String",
+                       i.year AS "year?: u32",
+                       i.tvshow_id AS "tvshow_id?: Id",
+                       t.title AS "tvshow_title?: String",
+                       i.season AS "season?: u32",
+                       i.episode AS "episode?: u32",
+                       COALESCE(t.thumbs, i.thumbs) AS "thumbs!: JVec<Thumb>"
+                FROM mediaitems_fts
+                JOIN mediaitems i ON i.id = mediaitems_fts.mediaitem_id
+                LEFT JOIN mediaitems t ON t.id = i.tvshow_id
+                WHERE mediaitems_fts MATCH ?
+                ORDER BY bm25(mediaitems_fts, 0.0, 10.0, 8.0, 1.0, 2.0, 3.0, 3.0, 1.0)
+                LIMIT ?"#,
+            query,
+            limit,
+        )
+        .fetch_all(dbh)
+        .await?;
+
+        let items = rows
+            .into_iter()
+            .map(|r| SearchResult {
+                id: r.id,
+                type_: r.type_,
+                collection_id: r.collection_id,
+                title: r.title,
+                year: r.year,
+                tvshow_id: r.tvshow_id,
+                tvshow_title: r.tvshow_title,
+                season: r.season,
+                episode: r.episode,
+                poster: r.thumbs.0.into_iter().find(|t| t.aspect == "poster"),
+            })
+            .collect();
+
+        Ok(items)
+    }
+}
+
+// Turn what the user typed into an FTS5 query. Punctuation is dropped,
+// so that the user can't produce FTS5 syntax errors.
+fn fts_query(query: &str) -> Option<String> {
+    let words = query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| format!("\"{}\"*", w))
+        .collect::<Vec<_>>();
+    (!words.is_empty()).then(|| words.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fts_query() {
+        assert_eq!(fts_query("  "), None);
+        assert_eq!(fts_query("De Aanslag").unwrap(), r#""De"* "Aanslag"*"#);
+        assert_eq!(fts_query(r#"l'été "OR" -x*"#).unwrap(), r#""l"* "été"* "OR"* "x"*"#);
+    }
+}
@@ -0,0 +1,451 @@
+use std::time::SystemTime;
+
+use anyhow::Result;
+use poem_openapi::Object;
+use serde::{Deserialize, Serialize};
+
+use crate::db;
+use crate::jvec::JVec;
+use crate::models::Thumb;
+use crate::util::{Id, SystemTimeToUnixTime};
+
+/// Watch progress of one movie or episode, for one user.
+#[derive(Object, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Seen {
+    /// Movie or episode id.
+    #[oai(read_only)]
+    pub mediaitem_id: Id,
+    /// Playback position in seconds.
+    pub position: f64,
+    /// Duration in seconds.
+    pub duration: Option<f64>,
+    /// Watched until the end.
+    pub completed: bool,
+    /// Last update (unix timestamp in ms).
+    pub updated: i64,
+}
+
+/// A movie or episode, with the user's watch progress.
+#[derive(Object, Serialize, Clone, Debug)]
+pub struct SeenItem {
+    /// Movie or episode id.
+    #[oai(read_only)]
+    pub id: Id,
+    /// "movie" or "episode".
+    #[oai(rename = "type")]
+    pub type_: String,
+    /// Collection id
+    pub collection_id: u32,
+    /// Title.
+    pub title: String,
+    /// Episode specific.
+    #[oai(read_only)]
+    pub tvshow_id: Option<Id>,
+    /// Episode specific.
+    pub tvshow_title: Option<String>,
+    /// Episode specific.
+    pub season: Option<u32>,
+    /// Episode specific.
+    pub episode: Option<u32>,
+    /// Episode specific: last episode, if the video contains more than one.
+    pub episode_last: Option<u32>,
+    /// Poster (movies) or thumbnail (episodes).
+    pub thumb: Option<Thumb>,
+    /// Watch progress, if any.
+    pub progress: Option<Seen>,
+}
+
+// The mediaitem part of a SeenItem, as read from the database.
+struct ItemRow {
+    id: Id,
+    type_: String,
+    collection_id: u32,
+    title: String,
+    tvshow_id: Option<Id>,
+    tvshow_title: Option<String>,
+    season: Option<u32>,
+    episode: Option<u32>,
+    episode_last: Option<u32>,
+}
+
+impl SeenItem {
+    fn new(row: ItemRow, thumbs: JVec<Thumb>, progress: Option<Seen>) -> SeenItem {
+        let aspect = if row.type_ == "episode" { "thumb" } else { "poster" };
+        let thumb = thumbs.0.into_iter().find(|t| t.aspect == aspect);
+        SeenItem {
+            id: row.id,
+            type_: row.type_,
+            collection_id: row.collection_id,
+            title: row.title,
+            tvshow_id: row.tvshow_id,
+            tvshow_title: row.tvshow_title,
+            season: row.season,
+            episode: row.episode,
+            episode_last: row.episode_last,
+            thumb,
+            progress,
+        }
+    }
+}
+
+impl Seen {
+    /// Get the progress of one item.
+    pub async fn get(
+        dbh: &mut db::TxnHandle<'_>,
+        user_id: i64,
+        mediaitem_id: Id,
+    ) -> Result<Option<Seen>> {
+        let r = sqlx::query_as!(
+            Seen,
+            r#"
+                SELECT mediaitem_id AS "mediaitem_id!: Id",
+                       position,
+                       duration,
+                       completed AS "completed!: bool",
+                       updated
+                FROM seen
+                WHERE user_id = ? AND mediaitem_id = ?"#,
+            user_id,
+            mediaitem_id,
+        )
+        .fetch_optional(dbh)
+        .await?;
+
+        Ok(r)
+    }
+
+    /// Get everything the user watched since `since` (unix timestamp in ms).
+    pub async fn since(dbh: &mut db::TxnHandle<'_>, user_id: i64, since: i64) -> Result<Vec<Seen>> {
+        let r = sqlx::query_as!(
+            Seen,
+            r#"
+                SELECT mediaitem_id AS "mediaitem_id!: Id",
+                       position,
+                       duration,
+                       completed AS "completed!: bool",
+                       updated
+                FROM seen
+                WHERE user_id = ? AND updated >= ?
+                ORDER BY updated DESC"#,
+            user_id,
+            since,
+        )
+        .fetch_all(dbh)
+        .await?;
+
+        Ok(r)
+    }
+
+    /// Insert or update the progress of one item.
+    ///
+    /// Sets `self.updated` to the current time.
+    pub async fn set(&mut self, txn: &mut db::TxnHandle<'_>, user_id: i64) -> Result<()> {
+        self.updated = SystemTime::now().unixtime_ms();
+        sqlx::query!(
+            r#"
+                INSERT INTO seen(user_id, mediaitem_id, position, duration, completed, updated)
+                VALUES(?, ?, ?, ?, ?, ?)
+                ON CONFLICT(user_id, mediaitem_id) DO UPDATE SET
+                    position = excluded.position,
+                    duration = excluded.duration,
+                    completed = excluded.completed,
+                    updated = excluded.updated"#,
+            user_id,
+            self.mediaitem_id,
+            self.position,
+            self.duration,
+            self.completed,
+            self.updated,
+        )
+        .execute(&mut *txn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Mark all episodes of a tvshow (or of one season) as seen or unseen.
+    ///
+    /// Unseen episodes are kept, with their progress reset, so that
+    /// the change shows up in `since`.
+    ///
+    /// Returns the number of episodes that were updated.
+    pub async fn mark_tvshow(
+        txn: &mut db::TxnHandle<'_>,
+        user_id: i64,
+        tvshow_id: Id,
+        season: Option<u32>,
+        seen: bool,
+    ) -> Result<u64> {
+        let now = SystemTime::now().unixtime_ms();
+        if !seen {
+            let r = sqlx::query!(
+                r#"
+                    UPDATE seen
+                    SET position = 0, completed = 0, updated = ?
+                    WHERE user_id = ? AND (completed != 0 OR position != 0)
+                      AND mediaitem_id IN (
+                          SELECT id FROM mediaitems
+                          WHERE tvshow_id = ? AND type = 'episode'
+                            AND (? IS NULL OR season = ?))"#,
+                now,
+                user_id,
+                tvshow_id,
+                season,
+                season,
+            )
+            .execute(&mut *txn)
+            .await?;
+            return Ok(r.rows_affected());
+        }
+
+        let r = sqlx::query!(
+            r#"
+                INSERT INTO seen(user_id, mediaitem_id, position, duration, completed, updated)
+                SELECT ?, id, 0, NULL, 1, ?
+                FROM mediaitems
+                WHERE tvshow_id = ? AND type = 'episode' AND deleted = 0
+                  AND (? IS NULL OR season = ?)
+                ON CONFLICT(user_id, mediaitem_id) DO UPDATE SET
+                    position = 0,
+                    completed = 1,
+                    updated = excluded.updated"#,
+            user_id,
+            now,
+            tvshow_id,
+            season,
+            season,
+        )
+        .execute(&mut *txn)
+        .await?;
+
+        Ok(r.rows_affected())
+    }
+
+    /// Partially watched movies and episodes, most recent first.
+    pub async fn continue_watching(
+        dbh: &mut db::TxnHandle<'_>,
+        user_id: i64,
+        limit: u32,
+    ) -> Result<Vec<SeenItem>> {
+        let rows = sqlx::query!(
+            r#"
+                SELECT i.id AS "id!: Id",
+                       i.type AS "type_!: String",
+                       i.collection_id AS "collection_id!: u32",
+                       i.title,
+                       i.tvshow_id AS "tvshow_id?: Id",
+                       t.title AS "tvshow_title?: String",
+                       i.season AS "season?: u32",
+                       i.episode AS "episode?: u32",
+                       i.episode_last AS "episode_last?: u32",
+                       i.thumbs AS "thumbs!: JVec<Thumb>",
+                       s.position,
+                       s.duration,
+                       s.completed AS "completed!: bool",
+                       s.updated
+                FROM seen s
+                JOIN mediaitems i ON i.id = s.mediaitem_id
+                LEFT JOIN mediaitems t ON t.id = i.tvshow_id
+                WHERE s.user_id = ? AND s.completed = 0 AND s.position > 0 AND i.deleted = 0
+                ORDER BY s.updated DESC
+                LIMIT ?"#,
+            user_id,
+            limit,
+        )
+        .fetch_all(dbh)
+        .await?;
+
+        let items = rows
+            .into_iter()
+            .map(|r| {
+                let seen = Seen {
+                    mediaitem_id: r.id,
+                    position: r.position,
+                    duration: r.duration,
+                    completed: r.completed,
+                    updated: r.updated,
+                };
+                let row = ItemRow {
+                    id: r.id,
+                    type_: r.type_,
+                    collection_id: r.collection_id,
+                    title: r.title,
+                    tvshow_id: r.tvshow_id,
+                    tvshow_title: r.tvshow_title,
+                    season: r.season,
+                    episode: r.episode,
+                    episode_last: r.episode_last,
+                };
+                SeenItem::new(row, r.thumbs, Some(seen))
+            })
+            .collect();
+
+        Ok(items)
+    }
+
+    /// For every tvshow the user has watched episodes of, the next episode to
+    /// watch: the first unwatched episode after the last watched one.
+    ///
+    /// Shows are ordered by most recent activity. Specials (season 0) are skipped,
+    /// and so are episodes that are part of a multi-episode video.
+    pub async fn next_up(dbh: &mut db::TxnHandle<'_>, user_id: i64) -> Result<Vec<SeenItem>> {
+        let rows = sqlx::query!(
+            r#"
+                SELECT i.id AS "id!: Id",
+                       i.collection_id AS "collection_id!: u32",
+                       i.title,
+                       i.tvshow_id AS "tvshow_id!: Id",
+                       t.title AS "tvshow_title?: String",
+                       i.season AS "season!: u32",
+                       i.episode AS "episode!: u32",
+                       i.episode_last AS "episode_last?: u32",
+                       i.thumbs AS "thumbs!: JVec<Thumb>",
+                       s.position AS "position?: f64",
+                       s.duration AS "duration?: f64",
+                       s.completed AS "completed?: bool",
+                       s.updated AS "updated?: i64"
+                FROM mediaitems i
+                LEFT JOIN seen s ON s.mediaitem_id = i.id AND s.user_id = ?
+                LEFT JOIN mediaitems t ON t.id = i.tvshow_id
+                WHERE i.type = 'episode' AND i.deleted = 0 AND i.season > 0
+                  AND i.tvshow_id IN (
+                      SELECT e.tvshow_id
+                      FROM seen x JOIN mediaitems e ON e.id = x.mediaitem_id
+                      WHERE x.user_id = ? AND e.type = 'episode'
+                        AND (x.completed != 0 OR x.position > 0))
+                ORDER BY i.tvshow_id, i.season, i.episode"#,
+            user_id,
+            user_id,
+        )
+        .fetch_all(dbh)
+        .await?;
+
+        // Split the rows up per tvshow. They are sorted by tvshow_id.
+        let mut shows = Vec::new();
+        let mut start = 0;
+        for idx in 1..=rows.len() {
+            if idx == rows.len() || rows[idx].tvshow_id != rows[start].tvshow_id {
+                shows.push(&rows[start..idx]);
+                start = idx;
+            }
+        }
+
+        let mut items = Vec::new();
+        for eps in shows {
+            // Skip episodes that are covered by the episode range of the one before.
+            let mut covered = (0, 0);
+            let eps = eps
+                .iter()
+                .filter(|e| {
+                    if e.season == covered.0 && e.episode <= covered.1 {
+                        return false;
+                    }
+                    covered = (e.season, e.episode_last.unwrap_or(e.episode));
+                    true
+                })
+                .collect::<Vec<_>>();
+
+            let last_activity = eps.iter().filter_map(|e| e.updated).max().unwrap_or(0);
+            let first = match eps.iter().rposition(|e| e.completed == Some(true)) {
+                Some(idx) => idx + 1,
+                None => 0,
+            };
+            let next = match eps[first..].iter().find(|e| e.completed != Some(true)) {
+                Some(next) => next,
+                None => continue,
+            };
+            let progress = next.updated.map(|updated| Seen {
+                mediaitem_id: next.id,
+                position: next.position.unwrap_or(0f64),
+                duration: next.duration,
+                completed: false,
+                updated,
+            });
+            let row = ItemRow {
+                id: next.id,
+                type_: "episode".to_string(),
+                collection_id: next.collection_id,
+                title: next.title.clone(),
+                tvshow_id: Some(next.tvshow_id),
+                tvshow_title: next.tvshow_title.clone(),
+                season: Some(next.season),
+                episode: Some(next.episode),
+                episode_last: next.episode_last,
+            };
+            items.push((last_activity, SeenItem::new(row, next.thumbs.clone(), progress)));
+        }
+        items.sort_by(|a, b| b.0.cmp(&a.0));
+
+        Ok(items.into_iter().map(|i| i.1).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::MediaItem;
+
+    fn episode(id: &str, episode: u32, episode_last: Option<u32>) -> MediaItem {
+        MediaItem {
+            type_: "episode".to_string(),
+            id: Id::from_str(id).unwrap(),
+            collection_id: 1,
+            title: id.to_string(),
+            season: Some(1),
+            episode: Some(episode),
+            episode_last,
+            tvshow_id: Some(Id::from_str("show").unwrap()),
+            ..MediaItem::default()
+        }
+    }
+
+    async fn completed(txn: &mut db::TxnHandle<'_>, id: &str) {
+        let mut seen = Seen {
+            mediaitem_id: Id::from_str(id).unwrap(),
+            position: 0.0,
+            duration: None,
+            completed: true,
+            updated: 0,
+        };
+        seen.set(txn, 1).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn next_up() {
+        let db = db::Db::connect("sqlite::memory:").await.unwrap();
+        let mut txn = db.handle.begin().await.unwrap();
+        sqlx::query("INSERT INTO users(id, username, password) VALUES(1, 'u', '!')")
+            .execute(&mut txn)
+            .await
+            .unwrap();
+        let show = MediaItem {
+            type_: "tvshow".to_string(),
+            id: Id::from_str("show").unwrap(),
+            collection_id: 1,
+            title: "Show".to_string(),
+            ..MediaItem::default()
+        };
+        show.insert(&mut txn).await.unwrap();
+        episode("e1", 1, None).insert(&mut txn).await.unwrap();
+        episode("e2", 2, Some(3)).insert(&mut txn).await.unwrap();
+        // Covered by the range of e2, so it is skipped.
+        episode("e3", 3, None).insert(&mut txn).await.unwrap();
+        episode("e4", 4, None).insert(&mut txn).await.unwrap();
+
+        completed(&mut txn, "e1").await;
+        let items = Seen::next_up(&mut txn, 1).await.unwrap();
+        let ids = items.iter().map(|i| i.id.to_string()).collect::<Vec<_>>();
+        assert_eq!(ids, ["e2"]);
+        assert_eq!(items[0].episode_last, Some(3));
+        assert_eq!(items[0].tvshow_title.as_deref(), Some("Show"));
+
+        completed(&mut txn, "e2").await;
+        let items = Seen::next_up(&mut txn, 1).await.unwrap();
+        let ids = items.iter().map(|i| i.id.to_string()).collect::<Vec<_>>();
+        assert_eq!(ids, ["e4"]);
+
+        // Nothing left to watch.
+        completed(&mut txn, "e4").await;
+        assert!(Seen::next_up(&mut txn, 1).await.unwrap().is_empty());
+    }
+}
@@ -1,14 +1,51 @@
 use anyhow::Result;
+use poem_openapi::Object;
 use std::time::{Duration, SystemTime};
 
 use crate::db;
-use crate::util::{some_or_return, Id, Rfc3339Time};
+use crate::models::ApiToken;
+use crate::util::{some_or_return, Id, Rfc3339Time, SystemTimeToUnixTime};
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct Session {
     pub username: String,
     pub user_id: i64,
     pub sessionid: String,
+    #[serde(default)]
+    pub admin: bool,
+    /// Set if this is not a login session but a personal access token.
+    #[serde(default)]
+    pub token_id: Option<i64>,
+    /// Token with read-only scope.
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+/// The client that created a session.
+#[derive(Debug, Default)]
+pub struct Client {
+    pub device: Option<String>,
+    pub user_agent: Option<String>,
+    pub remote_addr: Option<String>,
+}
+
+/// A login session, as shown to the user.
+#[derive(Object, Debug)]
+pub struct SessionInfo {
+    /// Id
+    pub id: i64,
+    /// Device name, as sent on login.
+    pub device: Option<String>,
+    /// User-Agent of the client that logged in.
+    pub user_agent: Option<String>,
+    /// IP address of the client that logged in.
+    pub remote_addr: Option<String>,
+    /// Time of login (unix timestamp in ms).
+    pub created: i64,
+    /// Time the session was last used (unix timestamp in ms).
+    pub updated: i64,
+    /// This is the session of the request.
+    pub current: bool,
 }
 
 impl Session {
@@ -17,18 +54,24 @@ impl Session {
         txn: &mut db::TxnHandle<'_>,
         user_id: i64,
         username: &str,
+        admin: bool,
+        client: &Client,
     ) -> Result<Session> {
         let sessionid = Id::new().to_string();
         let now = Rfc3339Time::new(SystemTime::now());
 
         sqlx::query!(
             r#"
-                INSERT INTO sessions(user_id, sessionid, created, updated)
-                VALUES(?, ?, ?, ?)"#,
+                INSERT INTO sessions(user_id, sessionid, created, updated,
+                                     device, user_agent, remote_addr)
+                VALUES(?, ?, ?, ?, ?, ?, ?)"#,
             user_id,
             sessionid,
             now,
             now,
+            client.device,
+            client.user_agent,
+            client.remote_addr,
         )
         .execute(&mut *txn)
         .await?;
@@ -41,6 +84,9 @@ impl Session {
             username: username.to_string(),
             user_id,
             sessionid,
+            admin,
+            token_id: None,
+            read_only: false,
         })
     }
 
@@ -56,7 +102,8 @@ impl Session {
                     u.username AS "username",
                     s.user_id AS "user_id",
                     s.sessionid AS "sessionid",
-                    s.updated AS "updated: Rfc3339Time"
+                    s.updated AS "updated: Rfc3339Time",
+                    u.admin AS "admin!: bool"
                 FROM sessions s, users u
                 WHERE s.user_id = u.id AND s.sessionid = ?"#,
             session_id
@@ -108,9 +155,40 @@ impl Session {
             username: s.username,
             user_id: s.user_id,
             sessionid: s.sessionid,
+            admin: s.admin,
+            token_id: None,
+            read_only: false,
         }))
     }
 
+    // Check that a session, or the token it was created from, still exists
+    // and has not expired. Unlike `find`, this does not count as activity.
+    pub async fn is_valid(
+        txn: &mut db::TxnHandle<'_>,
+        session: &Session,
+        timeout: Option<Duration>,
+    ) -> Result<bool> {
+        if let Some(token_id) = session.token_id {
+            return ApiToken::is_valid(txn, token_id).await;
+        }
+        let row = sqlx::query!(
+            r#"
+                SELECT updated AS "updated: Rfc3339Time"
+                FROM sessions
+                WHERE sessionid = ?"#,
+            session.sessionid
+        )
+        .fetch_optional(&mut *txn)
+        .await?;
+
+        let now = SystemTime::now();
+        Ok(match (row, timeout) {
+            (Some(row), Some(timeout)) => row.updated.as_systemtime() + timeout >= now,
+            (Some(_), None) => true,
+            (None, _) => false,
+        })
+    }
+
     // Delete session in the database.
     pub async fn delete(txn: &mut db::TxnHandle<'_>, sessionid: &str) -> Result<()> {
         sqlx::query!(
@@ -123,4 +201,92 @@ impl Session {
 
         Ok(())
     }
+
+    // List the sessions of a user that have not timed out.
+    pub async fn list(
+        txn: &mut db::TxnHandle<'_>,
+        user_id: i64,
+        current: &str,
+        timeout: Option<Duration>,
+    ) -> Result<Vec<SessionInfo>> {
+        let rows = sqlx::query!(
+            r#"
+                SELECT
+                    id AS "id!: i64",
+                    sessionid,
+                    device,
+                    user_agent,
+                    remote_addr,
+                    created AS "created: Rfc3339Time",
+                    updated AS "updated: Rfc3339Time"
+                FROM sessions
+                WHERE user_id = ?
+                ORDER BY updated DESC"#,
+            user_id
+        )
+        .fetch_all(&mut *txn)
+        .await?;
+
+        let now = SystemTime::now();
+        let sessions = rows
+            .into_iter()
+            .filter(|r| timeout.map(|t| r.updated.as_systemtime() + t >= now).unwrap_or(true))
+            .map(|r| SessionInfo {
+                id: r.id,
+                device: r.device,
+                user_agent: r.user_agent,
+                remote_addr: r.remote_addr,
+                created: r.created.as_systemtime().unixtime_ms(),
+                updated: r.updated.as_systemtime().unixtime_ms(),
+                current: r.sessionid == current,
+            })
+            .collect();
+        Ok(sessions)
+    }
+
+    // Delete a session of a user by id.
+    pub async fn delete_by_id(txn: &mut db::TxnHandle<'_>, user_id: i64, id: i64) -> Result<bool> {
+        let r = sqlx::query!(
+            r#"
+                DELETE FROM sessions WHERE id = ? AND user_id = ?"#,
+            id,
+            user_id,
+        )
+        .execute(&mut *txn)
+        .await?;
+
+        Ok(r.rows_affected() > 0)
+    }
+
+    // Delete all sessions of a user, except the session `keep`.
+    pub async fn delete_others(
+        txn: &mut db::TxnHandle<'_>,
+        user_id: i64,
+        keep: &str,
+    ) -> Result<u64> {
+        let r = sqlx::query!(
+            r#"
+                DELETE FROM sessions WHERE user_id = ? AND sessionid != ?"#,
+            user_id,
+            keep,
+        )
+        .execute(&mut *txn)
+        .await?;
+
+        Ok(r.rows_affected())
+    }
+
+    // Delete all sessions that timed out.
+    pub async fn purge(txn: &mut db::TxnHandle<'_>, timeout: Duration) -> Result<u64> {
+        let cutoff = Rfc3339Time::new(SystemTime::now() - timeout);
+        let r = sqlx::query!(
+            r#"
+                DELETE FROM sessions WHERE updated < ?"#,
+            cutoff
+        )
+        .execute(&mut *txn)
+        .await?;
+
+        Ok(r.rows_affected())
+    }
 }
@@ -0,0 +1,64 @@
+use std::time::SystemTime;
+
+use anyhow::Result;
+use rand::Rng;
+
+use crate::db;
+use crate::util::SystemTimeToUnixTime;
+
+/// Key to sign tokens with, in the `signing_keys` table.
+#[derive(Clone, Debug)]
+pub struct SigningKey {
+    pub id: i64,
+    pub key: Vec<u8>,
+    /// Unix timestamp in ms.
+    pub created: i64,
+}
+
+impl SigningKey {
+    /// All keys, newest first.
+    pub async fn all(txn: &mut db::TxnHandle<'_>) -> Result<Vec<SigningKey>> {
+        let keys = sqlx::query_as!(
+            SigningKey,
+            r#"
+                SELECT id AS "id!: i64", key, created
+                FROM signing_keys
+                ORDER BY id DESC"#
+        )
+        .fetch_all(&mut *txn)
+        .await?;
+        Ok(keys)
+    }
+
+    /// Create a new random key.
+    pub async fn create(txn: &mut db::TxnHandle<'_>) -> Result<SigningKey> {
+        let key = rand::thread_rng().gen::<[u8; 32]>().to_vec();
+        let created = SystemTime::now().unixtime_ms();
+        let id = sqlx::query!(
+            r#"
+                INSERT INTO signing_keys(key, created)
+                VALUES(?, ?)"#,
+            key,
+            created,
+        )
+        .execute(&mut *txn)
+        .await?
+        .last_insert_rowid();
+        Ok(SigningKey { id, key, created })
+    }
+
+    /// Delete the keys that were replaced before `time`.
+    ///
+    /// The key that was the newest at `time` is kept.
+    pub async fn expire(txn: &mut db::TxnHandle<'_>, time: i64) -> Result<u64> {
+        let r = sqlx::query!(
+            r#"
+                DELETE FROM signing_keys
+                WHERE created < (SELECT MAX(created) FROM signing_keys WHERE created <= ?)"#,
+            time,
+        )
+        .execute(&mut *txn)
+        .await?;
+        Ok(r.rows_affected())
+    }
+}
@@ -0,0 +1,97 @@
+use anyhow::Result;
+use poem_openapi::Object;
+use serde::Serialize;
+
+use crate::db;
+use crate::jvec::JVec;
+use crate::models::Thumb;
+use crate::util::Id;
+
+/// A movie, tvshow or episode that was added, updated or deleted.
+#[derive(Object, Serialize, Clone, Debug)]
+pub struct SyncItem {
+    /// Movie, tvshow or episode id.
+    #[oai(read_only)]
+    pub id: Id,
+    /// "movie", "tvshow" or "episode".
+    #[oai(rename = "type")]
+    pub type_: String,
+    /// Collection id
+    pub collection_id: u32,
+    /// Time of the change, unix timestamp in ms.
+    pub changed: i64,
+    /// The item was deleted. Only the id is meaningful.
+    pub deleted: bool,
+    /// Title.
+    pub title: String,
+    /// Year.
+    pub year: Option<u32>,
+    /// Episode specific.
+    #[oai(read_only)]
+    pub tvshow_id: Option<Id>,
+    /// Episode specific.
+    pub season: Option<u32>,
+    /// Episode specific.
+    pub episode: Option<u32>,
+    /// Poster of the movie or tvshow.
+    pub poster: Option<Thumb>,
+}
+
+impl SyncItem {
+    /// Items that changed after `since`, oldest change first.
+    ///
+    /// If `since` is 0 this is a full sync, and deleted items are left out.
+    pub async fn changes(
+        dbh: &mut db::TxnHandle<'_>,
+        since: i64,
+        collection_id: Option<i64>,
+        limit: u32,
+    ) -> Result<Vec<SyncItem>> {
+        let rows = sqlx::query!(
+            r#"
+                SELECT i.id AS "id!: Id",
+                       i.type AS "type_!: String",
+                       i.collection_id AS "collection_id!: u32",
+                       i.changed AS "changed!: i64",
+                       i.deleted AS "deleted!: bool",
+                       i.title AS "title!: String",
+                       i.year AS "year?: u32",
+                       i.tvshow_id AS "tvshow_id?: Id",
+                       i.season AS "season?: u32",
+                       i.episode AS "episode?: u32",
+                       i.thumbs AS "thumbs!: JVec<Thumb>"
+                FROM mediaitems i
+                WHERE i.changed > ?
+                  AND (? IS NULL OR i.collection_id = ?)
+                  AND (? > 0 OR i.deleted = 0)
+                ORDER BY i.changed
+                LIMIT ?"#,
+            since,
+            collection_id,
+            collection_id,
+            since,
+            limit,
+        )
+        .fetch_all(dbh)
+        .await?;
+
+        let items = rows
+            .into_iter()
+            .map(|r| SyncItem {
+                id: r.id,
+                type_: r.type_,
+                collection_id: r.collection_id,
+                changed: r.changed,
+                deleted: r.deleted,
+                title: r.title,
+                year: r.year,
+                tvshow_id: r.tvshow_id,
+                season: r.season,
+                episode: r.episode,
+                poster: r.thumbs.0.into_iter().find(|t| t.aspect == "poster"),
+            })
+            .collect();
+
+        Ok(items)
+    }
+}
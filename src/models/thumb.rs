@@ -1,10 +1,11 @@
-use std::io::BufReader;
+use std::io::{BufReader, Seek};
 
 use anyhow::Result;
 use poem_openapi::Object;
 use serde::{Deserialize, Serialize};
 use tokio::task;
 
+use crate::blurhash;
 use crate::collections::Collection;
 use crate::models::{is_default, FileInfo};
 use crate::sqlx::impl_sqlx_traits_for;
@@ -25,6 +26,13 @@ pub struct Thumb {
     pub quality: Option<u32>,
     #[serde(skip_serializing_if = "is_default")]
     pub season: Option<String>,
+    /// BlurHash of the image, to show as a placeholder while loading.
+    /// Empty if the image could not be decoded.
+    #[serde(skip_serializing_if = "is_default")]
+    pub blurhash: Option<String>,
+    /// Average colour of the image, as `#rrggbb`.
+    #[serde(skip_serializing_if = "is_default")]
+    pub color: Option<String>,
     #[serde(skip)]
     #[oai(skip)]
     pub state: ThumbState,
@@ -36,6 +44,7 @@ pub enum ThumbState {
     Deleted,
     #[default]
     Unchanged,
+    Updated,
     New,
 }
 
@@ -48,12 +57,17 @@ impl Thumb {
         aspect: &str,
         season: Option<String>,
     ) -> Result<Thumb> {
-        let (fileinfo, width, height) = task::block_in_place(move || {
+        let (fileinfo, width, height, placeholder) = task::block_in_place(move || {
             let (file, fileinfo) = FileInfo::open_std(basedir, path)?;
-            let ir = ::image::io::Reader::new(BufReader::with_capacity(32000, file));
+            let mut rdr = BufReader::with_capacity(32000, file);
+            let ir = ::image::io::Reader::new(&mut rdr);
             let (width, height) = ir.with_guessed_format()?.into_dimensions()?;
-            Ok::<_, anyhow::Error>((fileinfo, width, height))
+            rdr.rewind()?;
+            let placeholder = placeholder(rdr);
+            Ok::<_, anyhow::Error>((fileinfo, width, height, placeholder))
         })?;
+        let (blurhash, color) = placeholder.unzip();
+        let blurhash = Some(blurhash.unwrap_or_default());
 
         let ext = match fileinfo.path.rsplit_once(".").map(|t| t.1) {
             Some("tbn") => "jpg",
@@ -78,6 +92,8 @@ impl Thumb {
             height,
             quality: None,
             season,
+            blurhash,
+            color,
             state: ThumbState::New,
         })
     }
@@ -93,6 +109,18 @@ impl Thumb {
         let fileinfo = FileInfo::from_path(basedir, path).await?;
         if let Some(thumb) = thumbs.iter_mut().find(|t| t.fileinfo == fileinfo) {
             thumb.state = ThumbState::Unchanged;
+            if thumb.blurhash.is_none() {
+                // Indexed before we had placeholders. This is done only once,
+                // if the image can't be decoded the blurhash is left empty.
+                let placeholder = task::block_in_place(|| {
+                    let file = std::fs::File::open(&fileinfo.fullpath).ok()?;
+                    placeholder(BufReader::with_capacity(32000, file))
+                });
+                let (blurhash, color) = placeholder.unzip();
+                thumb.blurhash = Some(blurhash.unwrap_or_default());
+                thumb.color = color;
+                thumb.state = ThumbState::Updated;
+            }
             return Ok(false);
         }
         let id = thumbs.iter().fold(0, |acc, a| std::cmp::max(acc, a.image_id)) + 1;
@@ -111,3 +139,11 @@ impl Thumb {
         }
     }
 }
+
+// Decode the image and calculate its blurhash and average colour.
+fn placeholder<R: std::io::BufRead + Seek>(rdr: R) -> Option<(String, String)> {
+    let ir = ::image::io::Reader::new(rdr).with_guessed_format().ok()?;
+    let img = ir.decode().ok()?.thumbnail(32, 32).into_rgb8();
+    let (cx, cy) = if img.width() >= img.height() { (4, 3) } else { (3, 4) };
+    Some((blurhash::encode(&img, cx, cy), blurhash::average_color(&img)))
+}
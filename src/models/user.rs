@@ -10,6 +10,7 @@ pub struct User {
     pub username: String,
     pub password: String,
     pub email: Option<String>,
+    pub admin: bool,
 }
 
 #[derive(Debug, Default, Clone, Eq, PartialEq)]
@@ -18,6 +19,7 @@ pub struct UpdateUser {
     pub username: Option<String>,
     pub password: Option<String>,
     pub email: Option<String>,
+    pub admin: Option<bool>,
 }
 
 impl User {
@@ -26,7 +28,7 @@ impl User {
         let r = sqlx::query_as!(
             User,
             r#"
-                SELECT *
+                SELECT id, username, password, email, admin AS "admin!: bool"
                 FROM users
                 WHERE username = ?"#,
             username
@@ -42,26 +44,35 @@ impl User {
     }
 
     pub async fn get_users(dbh: &mut db::TxnHandle<'_>) -> Result<Vec<User>> {
-        let r = sqlx::query_as!(User, r#"SELECT id, username, '' AS password, email FROM users"#,)
-            .fetch_all(dbh)
-            .await?;
+        let r = sqlx::query_as!(
+            User,
+            r#"SELECT id, username, '' AS password, email, admin AS "admin!: bool" FROM users"#,
+        )
+        .fetch_all(dbh)
+        .await?;
 
         Ok(r)
     }
 
-    pub async fn insert(&mut self, txn: &mut db::TxnHandle<'_>) -> Result<i64> {
+    pub fn hash_password(password: &str) -> Result<String> {
         let params = Sha512Params::default();
-        let hashed = ok_or_return!(sha512_simple(&self.password, &params), |_| {
+        let hashed = ok_or_return!(sha512_simple(password, &params), |_| {
             bail!("unexpected error in sha-crypt::sha512_simple");
         });
+        Ok(hashed)
+    }
+
+    pub async fn insert(&mut self, txn: &mut db::TxnHandle<'_>) -> Result<i64> {
+        let hashed = User::hash_password(&self.password)?;
 
         let id = sqlx::query!(
             r#"
-                INSERT INTO users(username, password, email)
-                VALUES(?, ?, ?)"#,
+                INSERT INTO users(username, password, email, admin)
+                VALUES(?, ?, ?, ?)"#,
             self.username,
             hashed,
             self.email,
+            self.admin,
         )
         .execute(&mut *txn)
         .await?
@@ -78,6 +89,12 @@ impl User {
             return Ok(false);
         }
 
+        sqlx::query!(r#"DELETE FROM sessions WHERE user_id = ?"#, user_id)
+            .execute(&mut *txn)
+            .await?;
+        sqlx::query!(r#"DELETE FROM api_tokens WHERE user_id = ?"#, user_id)
+            .execute(&mut *txn)
+            .await?;
         sqlx::query!(r#"DELETE FROM users WHERE id = ?"#, user_id).execute(&mut *txn).await?;
 
         Ok(true)
@@ -87,13 +104,7 @@ impl User {
 impl UpdateUser {
     pub async fn update(&self, txn: &mut db::TxnHandle<'_>) -> Result<bool> {
         let hashed = match self.password.as_ref() {
-            Some(password) => {
-                let params = Sha512Params::default();
-                let hashed = ok_or_return!(sha512_simple(password, &params), |_| {
-                    bail!("unexpected error in sha-crypt::sha512_simple");
-                });
-                Some(hashed)
-            },
+            Some(password) => Some(User::hash_password(password)?),
             None => None,
         };
         let mut sql = "UPDATE users SET ".to_string();
@@ -107,6 +118,9 @@ impl UpdateUser {
         if self.email.is_some() {
             args.push("email = ?");
         }
+        if self.admin.is_some() {
+            args.push("admin = ?");
+        }
         sql.push_str(&args.join(", "));
         sql.push_str(" WHERE id = ?");
 
@@ -120,6 +134,9 @@ impl UpdateUser {
         if let Some(email) = self.email.as_ref() {
             q = q.bind(email);
         }
+        if let Some(admin) = self.admin {
+            q = q.bind(admin);
+        }
         q = q.bind(&self.id);
 
         let nr = q.execute(&mut *txn).await?.rows_affected();
@@ -0,0 +1,264 @@
+//! Rate limiting.
+//!
+//! `RateLimiter` limits the number of requests per client address, with
+//! a token bucket. It is off unless `rate-limit` is set in the config.
+//!
+//! `LoginThrottle` slows down password guessing. After a few failed
+//! logins for a username, or from an address, the next attempt has to
+//! wait. The wait doubles with every failure, up to 15 minutes. Every
+//! attempt counts as a failure until it succeeds, so that parallel
+//! attempts are throttled as well.
+//!
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Maximum number of addresses or usernames that are tracked.
+const MAX_ENTRIES: usize = 10000;
+
+// Failed logins before the first wait.
+const FREE_ATTEMPTS: u32 = 3;
+// First and maximum wait after a failed login.
+const MIN_DELAY: Duration = Duration::from_secs(1);
+const MAX_DELAY: Duration = Duration::from_secs(15 * 60);
+// Failed logins are forgotten after this time.
+const FORGET_AFTER: Duration = Duration::from_secs(3600);
+
+/// Value of the `Retry-After` header: whole seconds, rounded up.
+pub fn retry_after(wait: Duration) -> u64 {
+    wait.as_secs() + (wait.subsec_nanos() > 0) as u64
+}
+
+// A map with a maximum size. When it is full, the oldest entry is evicted.
+struct BoundedMap<K, V> {
+    max: usize,
+    map: HashMap<K, (u64, V)>,
+    // Keys in the order they were inserted, with a sequence number to
+    // recognize keys that were removed and inserted again.
+    order: VecDeque<(u64, K)>,
+    seq: u64,
+}
+
+impl<K: Hash + Eq + Clone, V> BoundedMap<K, V> {
+    fn new(max: usize) -> BoundedMap<K, V> {
+        BoundedMap {
+            max,
+            map: HashMap::new(),
+            order: VecDeque::new(),
+            seq: 0,
+        }
+    }
+
+    fn get(&self, key: &K) -> Option<&V> {
+        self.map.get(key).map(|e| &e.1)
+    }
+
+    fn get_or_insert_with(&mut self, key: K, value: impl FnOnce() -> V) -> &mut V {
+        if !self.map.contains_key(&key) {
+            while self.map.len() >= self.max {
+                self.evict();
+            }
+            self.seq += 1;
+            self.order.push_back((self.seq, key.clone()));
+            self.map.insert(key.clone(), (self.seq, value()));
+        }
+        &mut self.map.get_mut(&key).unwrap().1
+    }
+
+    fn remove(&mut self, key: &K) {
+        self.map.remove(key);
+        // Drop the keys that are gone from `order` once in a while.
+        if self.order.len() >= 2 * self.max {
+            let map = &self.map;
+            self.order.retain(|(seq, k)| map.get(k).map(|e| e.0 == *seq).unwrap_or(false));
+        }
+    }
+
+    fn evict(&mut self) {
+        while let Some((seq, key)) = self.order.pop_front() {
+            if self.map.get(&key).map(|e| e.0 == seq).unwrap_or(false) {
+                self.map.remove(&key);
+                return;
+            }
+        }
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Limit requests per client address.
+#[derive(Clone)]
+pub struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    buckets: Arc<Mutex<BoundedMap<IpAddr, Bucket>>>,
+}
+
+impl RateLimiter {
+    /// Allow `rate` requests per second on average, and bursts of `burst` requests.
+    pub fn new(rate: f64, burst: u32) -> RateLimiter {
+        RateLimiter {
+            rate,
+            burst: (burst as f64).max(1.0),
+            buckets: Arc::new(Mutex::new(BoundedMap::new(MAX_ENTRIES))),
+        }
+    }
+
+    /// Count a request from `addr`. If it is over the limit, returns
+    /// how long to wait.
+    pub fn check(&self, addr: IpAddr) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        let burst = self.burst;
+        let bucket = buckets.get_or_insert_with(addr, || Bucket { tokens: burst, updated: now });
+        let elapsed = (now - bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate))
+        }
+    }
+}
+
+struct Failures {
+    count: u32,
+    last: Instant,
+}
+
+impl Failures {
+    // Time until the next attempt is allowed.
+    fn wait(&self, now: Instant) -> Option<Duration> {
+        if self.count <= FREE_ATTEMPTS {
+            return None;
+        }
+        let shift = (self.count - FREE_ATTEMPTS - 1).min(16);
+        let delay = (MIN_DELAY * (1 << shift)).min(MAX_DELAY);
+        (self.last + delay).checked_duration_since(now).filter(|d| !d.is_zero())
+    }
+}
+
+/// Throttle failed logins per username and per client address.
+#[derive(Clone)]
+pub struct LoginThrottle {
+    failures: Arc<Mutex<BoundedMap<String, Failures>>>,
+}
+
+impl LoginThrottle {
+    pub fn new() -> LoginThrottle {
+        LoginThrottle {
+            failures: Arc::new(Mutex::new(BoundedMap::new(MAX_ENTRIES))),
+        }
+    }
+
+    fn keys(username: Option<&str>, addr: Option<IpAddr>) -> Vec<String> {
+        let mut keys = Vec::new();
+        if let Some(username) = username {
+            keys.push(format!("user:{}", username));
+        }
+        if let Some(addr) = addr {
+            keys.push(format!("addr:{}", addr));
+        }
+        keys
+    }
+
+    /// Count a login attempt as failed, until `succeeded` is called.
+    ///
+    /// `username` should only be set if the user exists, so that guessing
+    /// usernames can't fill up the table. If the login must wait, it is
+    /// not counted and returns how long.
+    pub fn attempt(&self, username: Option<&str>, addr: Option<IpAddr>) -> Result<(), Duration> {
+        let now = Instant::now();
+        let keys = LoginThrottle::keys(username, addr);
+        let mut failures = self.failures.lock().unwrap();
+        let wait = keys.iter().filter_map(|key| failures.get(key).and_then(|f| f.wait(now))).max();
+        if let Some(wait) = wait {
+            return Err(wait);
+        }
+
+        for key in keys {
+            let f = failures.get_or_insert_with(key, || Failures { count: 0, last: now });
+            if now - f.last >= FORGET_AFTER {
+                f.count = 0;
+            }
+            f.count += 1;
+            f.last = now;
+        }
+        Ok(())
+    }
+
+    /// Forget the failed logins after a successful one.
+    pub fn succeeded(&self, username: &str, addr: Option<IpAddr>) {
+        let mut failures = self.failures.lock().unwrap();
+        for key in LoginThrottle::keys(Some(username), addr) {
+            failures.remove(&key);
+        }
+    }
+}
+
+impl Default for LoginThrottle {
+    fn default() -> LoginThrottle {
+        LoginThrottle::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_limiter() {
+        let limiter = RateLimiter::new(1.0, 3);
+        let addr: IpAddr = "192.168.1.10".parse().unwrap();
+        let other: IpAddr = "192.168.1.11".parse().unwrap();
+        for _ in 0..3 {
+            assert!(limiter.check(addr).is_ok());
+        }
+        let wait = limiter.check(addr).unwrap_err();
+        assert!(wait > Duration::from_millis(900) && wait <= Duration::from_secs(1));
+        assert!(limiter.check(other).is_ok());
+    }
+
+    #[test]
+    fn login_throttle() {
+        let throttle = LoginThrottle::new();
+        let addr: IpAddr = "192.168.1.10".parse().unwrap();
+        for _ in 0..FREE_ATTEMPTS {
+            assert!(throttle.attempt(Some("alice"), Some(addr)).is_ok());
+        }
+        // Attempts that are still running count as failed.
+        assert!(throttle.attempt(Some("alice"), Some(addr)).is_ok());
+        assert!(throttle.attempt(Some("alice"), None).unwrap_err() <= MIN_DELAY);
+        // The address is throttled for other usernames too.
+        assert!(throttle.attempt(Some("bob"), Some(addr)).is_err());
+        assert!(throttle.attempt(Some("bob"), None).is_ok());
+        throttle.succeeded("alice", Some(addr));
+        assert!(throttle.attempt(Some("alice"), Some(addr)).is_ok());
+    }
+
+    #[test]
+    fn bounded_map() {
+        let mut map = BoundedMap::new(2);
+        *map.get_or_insert_with(1, || 0) += 1;
+        map.get_or_insert_with(2, || 0);
+        map.remove(&1);
+        map.get_or_insert_with(1, || 0);
+        // Full, so the oldest entry (2) is evicted.
+        map.get_or_insert_with(3, || 0);
+        assert_eq!(map.get(&2), None);
+        assert_eq!(map.get(&1), Some(&0));
+        assert_eq!(map.map.len(), 2);
+        for i in 4..100 {
+            map.get_or_insert_with(i, || i);
+            map.remove(&(i - 1));
+        }
+        assert!(map.order.len() < 4);
+    }
+}
@@ -0,0 +1,253 @@
+//! Background collection rescans.
+//!
+//! Every collection that has a `scan-interval` set in the config file
+//! is rescanned periodically. A rescan can also be started through the API.
+//! A collection is never scanned twice at the same time.
+//!
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use poem_openapi::Object;
+use tokio::time::MissedTickBehavior;
+
+use crate::collections::Collection;
+use crate::config::Config;
+use crate::db::{Db, ScanCounts};
+use crate::events::{Event, EventType, Events};
+use crate::util::SystemTimeToUnixTime;
+
+/// Status of the (last) scan of a collection.
+///
+/// A scan is a full rescan, or an update of a few directories that the
+/// watcher saw change.
+#[derive(Object, Clone, Debug, Default)]
+pub struct ScanStatus {
+    /// Collection id
+    pub collection_id: u32,
+    /// A scan is running right now.
+    pub running: bool,
+    /// Start of the last scan (unix timestamp in ms).
+    pub last_started: Option<i64>,
+    /// End of the last scan (unix timestamp in ms).
+    pub last_finished: Option<i64>,
+    /// Error message, if the last scan failed.
+    pub last_error: Option<String>,
+    /// Number of items added by the last scan.
+    pub added: u64,
+    /// Number of items updated by the last scan.
+    pub updated: u64,
+    /// Number of items deleted by the last scan.
+    pub deleted: u64,
+}
+
+#[derive(Clone)]
+pub struct Scanner {
+    db: Db,
+    config: Arc<Config>,
+    events: Events,
+    status: Arc<Mutex<HashMap<u32, ScanStatus>>>,
+}
+
+impl Scanner {
+    pub fn new(db: Db, config: Arc<Config>, events: Events) -> Scanner {
+        let mut status = HashMap::new();
+        for coll in &config.collections {
+            let collection_id = coll.collection_id;
+            status.insert(collection_id, ScanStatus { collection_id, ..ScanStatus::default() });
+        }
+        Scanner {
+            db,
+            config,
+            events,
+            status: Arc::new(Mutex::new(status)),
+        }
+    }
+
+    /// Start the periodic rescans, one task per collection.
+    pub fn start(&self) {
+        for coll in &self.config.collections {
+            let interval = match coll.scan_interval {
+                Some(interval) => interval,
+                None => continue,
+            };
+            log::info!("scanner: rescanning {} every {:?}", coll.name, interval);
+            let this = self.clone();
+            let collection_id = coll.collection_id;
+            tokio::spawn(async move {
+                let mut timer = tokio::time::interval(interval);
+                timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                loop {
+                    timer.tick().await;
+                    if !this.scan(collection_id).await {
+                        log::info!("scanner: collection {}: scan already running", collection_id);
+                    }
+                }
+            });
+        }
+    }
+
+    /// Start a rescan in the background.
+    ///
+    /// Returns `false` if a scan of this collection is already running.
+    pub fn trigger(&self, collection_id: u32) -> bool {
+        let guard = match self.try_begin(collection_id) {
+            Some(guard) => guard,
+            None => return false,
+        };
+        let this = self.clone();
+        tokio::spawn(async move {
+            this.run(guard).await;
+        });
+        true
+    }
+
+    /// Update a few items of a collection.
+    ///
+    /// Returns `Ok(false)` if a scan of this collection is running.
+    pub async fn update_dirs(&self, collection_id: u32, dirs: &[String]) -> anyhow::Result<bool> {
+        let guard = match self.try_begin(collection_id) {
+            Some(guard) => guard,
+            None => return Ok(false),
+        };
+
+        let coll = self.config.get_collection(collection_id).unwrap();
+        let res = self.db.update_directories(coll, dirs).await;
+        guard.finish(&res);
+        let counts = res?;
+        log::debug!("scanner: collection {}: {}", coll.name, summary(&counts));
+        self.send_changes(counts);
+        Ok(true)
+    }
+
+    /// Get the scan status of a collection.
+    pub fn status(&self, collection_id: u32) -> Option<ScanStatus> {
+        self.status.lock().unwrap().get(&collection_id).cloned()
+    }
+
+    // Rescan a collection and wait for it to finish.
+    async fn scan(&self, collection_id: u32) -> bool {
+        let guard = match self.try_begin(collection_id) {
+            Some(guard) => guard,
+            None => return false,
+        };
+        self.run(guard).await;
+        true
+    }
+
+    // Mark the collection as being scanned, unless it already is.
+    fn try_begin(&self, collection_id: u32) -> Option<ScanGuard> {
+        let mut status = self.status.lock().unwrap();
+        let status = status.get_mut(&collection_id)?;
+        if status.running {
+            return None;
+        }
+        status.running = true;
+        status.last_started = Some(SystemTime::now().unixtime_ms());
+        Some(ScanGuard {
+            status: self.status.clone(),
+            collection_id,
+            done: false,
+        })
+    }
+
+    async fn run(&self, guard: ScanGuard) {
+        let collection_id = guard.collection_id;
+        let coll: &Collection = self.config.get_collection(collection_id).unwrap();
+        log::info!("scanner: scanning collection {}", coll.name);
+        self.events.send(Event::scan(EventType::ScanStarted, collection_id));
+        let res = self.db.update_collection(coll).await;
+        if let Ok(counts) = &res {
+            log::info!("scanner: collection {}: {}", coll.name, summary(counts));
+        }
+        guard.finish(&res);
+
+        if let Ok(counts) = res {
+            self.send_changes(counts);
+        }
+        self.events.send(Event::scan(EventType::ScanFinished, collection_id));
+    }
+
+    // Tell clients what was changed. Only after the commit, so that
+    // they see the changes when they refetch the items.
+    fn send_changes(&self, counts: ScanCounts) {
+        for event in counts.changes {
+            self.events.send(event);
+        }
+    }
+}
+
+// Marks a collection as being scanned. The mark is removed when this is
+// dropped, also when the scan panics or its task is cancelled.
+struct ScanGuard {
+    status: Arc<Mutex<HashMap<u32, ScanStatus>>>,
+    collection_id: u32,
+    done: bool,
+}
+
+impl ScanGuard {
+    // Record the result of the scan.
+    fn finish(mut self, res: &anyhow::Result<ScanCounts>) {
+        let mut status = self.status.lock().unwrap();
+        let status = status.get_mut(&self.collection_id).unwrap();
+        status.last_finished = Some(SystemTime::now().unixtime_ms());
+        match res {
+            Ok(counts) => {
+                status.last_error = None;
+                status.added = counts.added;
+                status.updated = counts.updated;
+                status.deleted = counts.deleted;
+            },
+            Err(e) => {
+                status.last_error = Some(e.to_string());
+                status.added = 0;
+                status.updated = 0;
+                status.deleted = 0;
+            },
+        }
+        self.done = true;
+    }
+}
+
+impl Drop for ScanGuard {
+    fn drop(&mut self) {
+        let mut status = self.status.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(status) = status.get_mut(&self.collection_id) {
+            status.running = false;
+            if !self.done {
+                status.last_finished = Some(SystemTime::now().unixtime_ms());
+                status.last_error = Some("scan was aborted".to_string());
+            }
+        }
+    }
+}
+
+fn summary(counts: &ScanCounts) -> String {
+    format!("{} added, {} updated, {} deleted", counts.added, counts.updated, counts.deleted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guard_clears_running() {
+        let status = Arc::new(Mutex::new(HashMap::new()));
+        let running = ScanStatus {
+            collection_id: 1,
+            running: true,
+            ..ScanStatus::default()
+        };
+        status.lock().unwrap().insert(1, running);
+
+        // Dropped without finishing, like when a scan panics.
+        drop(ScanGuard {
+            status: status.clone(),
+            collection_id: 1,
+            done: false,
+        });
+        let s = status.lock().unwrap().get(&1).cloned().unwrap();
+        assert!(!s.running);
+        assert!(s.last_error.is_some());
+    }
+}
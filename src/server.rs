@@ -4,6 +4,7 @@ use std::time::{Duration, SystemTime};
 
 use anyhow::Context;
 use poem::{
+    http::{header, Method, StatusCode},
     listener::{Listener, RustlsCertificate, RustlsConfig, TcpListener},
     Endpoint, EndpointExt, IntoResponse, Request, Response, Result, Route, Server,
 };
@@ -12,14 +13,24 @@ use poem_openapi::{auth::ApiKey, OpenApiService, SecurityScheme};
 use crate::api::Api;
 use crate::config::Config;
 use crate::db::Db;
+use crate::events::Events;
 use crate::media;
 use crate::models;
+use crate::ratelimit::{self, LoginThrottle, RateLimiter};
+use crate::scanner::Scanner;
+use crate::signing::Signer;
 use crate::util::ok_or_return;
+use crate::watcher;
 
 #[derive(Clone)]
 pub struct SharedState {
     pub db: Db,
     pub config: Arc<Config>,
+    pub scanner: Scanner,
+    pub events: Events,
+    pub signer: Signer,
+    pub login_throttle: LoginThrottle,
+    pub rate_limiter: Option<RateLimiter>,
 }
 
 /// ApiKey authorization
@@ -34,16 +45,34 @@ pub struct SessionFC(pub models::Session);
 
 async fn api_checker(req: &Request, api_key: ApiKey) -> Option<models::Session> {
     let state = req.data::<SharedState>().unwrap();
-    let api_key = api_key.key.as_str();
-    let timeout = state.config.session.timeout;
     // println!("api key sent: {:?}", api_key);
+    let session = find_session(state, api_key.key.as_str()).await?;
+
+    // Read-only tokens can only be used to get things.
+    if session.read_only && req.method() != Method::GET && req.method() != Method::HEAD {
+        log::info!("api_checker: read-only token used for {} {}", req.method(), req.uri());
+        return None;
+    }
+    Some(session)
+}
+
+/// Find a session by session id or personal access token, and check
+/// that it has not expired.
+pub async fn find_session(state: &SharedState, api_key: &str) -> Option<models::Session> {
+    let timeout = state.config.session.timeout;
 
     let mut txn = ok_or_return!(state.db.handle.begin().await, |err| {
         log::error!("api_checker: {}", err);
         None
     });
 
-    match models::Session::find(&mut txn, api_key, timeout).await {
+    let session = if api_key.starts_with(models::ApiToken::PREFIX) {
+        models::ApiToken::find(&mut txn, api_key).await
+    } else {
+        models::Session::find(&mut txn, api_key, timeout).await
+    };
+
+    match session {
         Ok(Some(session)) => {
             if txn.commit().await.is_ok() {
                 Some(session)
@@ -111,7 +140,30 @@ pub async fn serve(cfg: Config, db: Db) -> anyhow::Result<()> {
         listener = listener.combine(l).boxed();
     }
 
-    let state = SharedState { db, config: Arc::new(cfg) };
+    let config = Arc::new(cfg);
+    let events = Events::new();
+    let scanner = Scanner::new(db.clone(), config.clone(), events.clone());
+    scanner.start();
+    watcher::start(&config, &scanner);
+    let signer = Signer::load(&db).await?;
+    signer.start(db.clone());
+    if let Some(timeout) = config.session.timeout {
+        purge_sessions(db.clone(), timeout);
+    }
+    let rate_limiter = config.server.rate_limit.map(|rate| {
+        let burst = config.server.rate_limit_burst.unwrap_or(100);
+        RateLimiter::new(rate, burst)
+    });
+    let login_throttle = LoginThrottle::new();
+    let state = SharedState {
+        db,
+        config,
+        scanner,
+        events,
+        signer,
+        login_throttle,
+        rate_limiter,
+    };
 
     let api_service = OpenApiService::new(Api::new(state.clone()), "Notflix", "0.1")
         .server("https://mx2.high5.nl:3001/api");
@@ -124,6 +176,7 @@ pub async fn serve(cfg: Config, db: Db) -> anyhow::Result<()> {
         // .nest("/spec", spec)
         .nest("/media", media)
         .nest("/", ui)
+        .around(rate_limit)
         .around(log)
         .data(state);
 
@@ -132,6 +185,27 @@ pub async fn serve(cfg: Config, db: Db) -> anyhow::Result<()> {
     Ok(())
 }
 
+// Delete the sessions that timed out, every hour.
+fn purge_sessions(db: Db, timeout: Duration) {
+    tokio::spawn(async move {
+        let mut timer = tokio::time::interval(Duration::from_secs(3600));
+        loop {
+            timer.tick().await;
+            let res = async {
+                let mut txn = db.handle.begin().await?;
+                let count = models::Session::purge(&mut txn, timeout).await?;
+                txn.commit().await?;
+                Ok::<_, anyhow::Error>(count)
+            };
+            match res.await {
+                Ok(0) => {},
+                Ok(count) => log::info!("purge_sessions: deleted {} expired sessions", count),
+                Err(e) => log::error!("purge_sessions: {}", e),
+            }
+        }
+    });
+}
+
 #[derive(PartialEq)]
 struct TlsFileState {
     cert_size: u64,
@@ -182,6 +256,21 @@ async fn load_tls_config(
     Ok(Some(tls_config))
 }
 
+async fn rate_limit<E: Endpoint>(next: E, req: Request) -> Result<Response> {
+    let state = req.data::<SharedState>().unwrap();
+    let addr = req.remote_addr().as_socket_addr().map(|a| a.ip());
+    if let (Some(limiter), Some(addr)) = (&state.rate_limiter, addr) {
+        if let Err(wait) = limiter.check(addr) {
+            return Ok(Response::builder()
+                .status(StatusCode::TOO_MANY_REQUESTS)
+                .header(header::RETRY_AFTER, ratelimit::retry_after(wait))
+                .finish());
+        }
+    }
+    let resp = next.call(req).await?;
+    Ok(resp.into_response())
+}
+
 async fn log<E: Endpoint>(next: E, req: Request) -> Result<Response> {
     // store request data.
     let start = std::time::Instant::now();
@@ -0,0 +1,196 @@
+//! Signed tokens for the `/media` routes.
+//!
+//! Devices like a Chromecast can't send our session cookie, so they
+//! get a token in the query string instead. The token is bound to a
+//! user, a collection, a path and an expiry time, and optionally to the
+//! IP address of the client. It is also valid for everything below that
+//! path, so that the HLS playlists and segments of a video can be fetched
+//! with the token of the video file.
+//!
+//! The signing keys are kept in the database, and rotated every day.
+//!
+use std::net::IpAddr;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use tokio::time::MissedTickBehavior;
+
+use crate::db::Db;
+use crate::models::SigningKey;
+use crate::util::SystemTimeToUnixTime;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// How long a token is valid. Long enough to watch a movie.
+pub const TOKEN_LIFETIME: Duration = Duration::from_secs(6 * 3600);
+// How long a key is used to sign new tokens.
+const KEY_LIFETIME: Duration = Duration::from_secs(24 * 3600);
+// How often to check if the key needs to be rotated.
+const ROTATE_CHECK: Duration = Duration::from_secs(3600);
+
+#[derive(Clone)]
+pub struct Signer {
+    // Newest first.
+    keys: Arc<RwLock<Vec<SigningKey>>>,
+}
+
+impl Signer {
+    /// Load the keys from the database. Creates a key if needed.
+    pub async fn load(db: &Db) -> Result<Signer> {
+        let signer = Signer { keys: Arc::new(RwLock::new(Vec::new())) };
+        signer.rotate(db).await?;
+        Ok(signer)
+    }
+
+    /// Rotate the key every day, in the background.
+    pub fn start(&self, db: Db) {
+        let this = self.clone();
+        tokio::spawn(async move {
+            let mut timer = tokio::time::interval(ROTATE_CHECK);
+            timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                timer.tick().await;
+                if let Err(e) = this.rotate(&db).await {
+                    log::error!("signer: failed to rotate key: {}", e);
+                }
+            }
+        });
+    }
+
+    // Create a new key if the newest key is too old, and delete the
+    // keys that no unexpired token can have been signed with.
+    async fn rotate(&self, db: &Db) -> Result<()> {
+        let now = SystemTime::now();
+        let mut txn = db.handle.begin().await?;
+        let mut keys = SigningKey::all(&mut txn).await?;
+        let too_old = (now - KEY_LIFETIME).unixtime_ms();
+        if keys.first().map(|k| k.created < too_old).unwrap_or(true) {
+            log::info!("signer: creating new signing key");
+            keys.insert(0, SigningKey::create(&mut txn).await?);
+        }
+        let expired = (now - TOKEN_LIFETIME).unixtime_ms();
+        if SigningKey::expire(&mut txn, expired).await? > 0 {
+            keys = SigningKey::all(&mut txn).await?;
+        }
+        txn.commit().await?;
+
+        *self.keys.write().unwrap() = keys;
+        Ok(())
+    }
+
+    /// Create a token for `path` in a collection.
+    ///
+    /// If `ip` is set, the token is only valid for requests from that address.
+    /// Returns the token and the time it expires (unix timestamp in ms).
+    pub fn sign(
+        &self,
+        user_id: i64,
+        collection_id: u32,
+        path: &str,
+        ip: Option<IpAddr>,
+    ) -> (String, i64) {
+        let keys = self.keys.read().unwrap();
+        let key = &keys[0];
+        let expires = (SystemTime::now() + TOKEN_LIFETIME).unixtime_ms();
+        let path = path.trim_matches('/');
+        let fields = format!("{}.{}.{}.{}", key.id, user_id, expires, ip.is_some() as u8);
+        let mac = mac(&key.key, &fields, ip, collection_id, path);
+        let sig = hex(&mac.finalize().into_bytes());
+        (format!("{}.{}", fields, sig), expires)
+    }
+
+    /// Check a token for `path` in a collection, for a request from `ip`.
+    ///
+    /// Returns the user id if the token is valid for `path` or one of its parents.
+    pub fn verify(
+        &self,
+        token: &str,
+        collection_id: u32,
+        path: &str,
+        ip: Option<IpAddr>,
+    ) -> Option<i64> {
+        let (fields, sig) = token.rsplit_once('.')?;
+        let sig = unhex(sig)?;
+        let mut parts = fields.split('.');
+        let key_id = parts.next()?.parse::<i64>().ok()?;
+        let user_id = parts.next()?.parse::<i64>().ok()?;
+        let expires = parts.next()?.parse::<i64>().ok()?;
+        let ip = match parts.next()? {
+            "0" => None,
+            "1" => Some(ip?),
+            _ => return None,
+        };
+        if parts.next().is_some() || expires < SystemTime::now().unixtime_ms() {
+            return None;
+        }
+
+        let keys = self.keys.read().unwrap();
+        let key = keys.iter().find(|k| k.id == key_id)?;
+        let mut path = path.trim_matches('/');
+        loop {
+            let mac = mac(&key.key, fields, ip, collection_id, path);
+            if mac.verify_slice(&sig).is_ok() {
+                return Some(user_id);
+            }
+            path = path.rsplit_once('/')?.0;
+        }
+    }
+}
+
+fn mac(key: &[u8], fields: &str, ip: Option<IpAddr>, collection_id: u32, path: &str) -> HmacSha256 {
+    let ip = ip.map(|ip| ip.to_canonical().to_string()).unwrap_or_default();
+    let mut mac = HmacSha256::new_from_slice(key).unwrap();
+    mac.update(format!("{}:{}:{}:{}", fields, ip, collection_id, path).as_bytes());
+    mac
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex(data: &str) -> Option<Vec<u8>> {
+    if data.len() % 2 != 0 || !data.is_ascii() {
+        return None;
+    }
+    (0..data.len()).step_by(2).map(|i| u8::from_str_radix(&data[i..i + 2], 16).ok()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signer(id: i64) -> Signer {
+        let key = SigningKey { id, key: vec![id as u8; 32], created: 0 };
+        Signer { keys: Arc::new(RwLock::new(vec![key])) }
+    }
+
+    #[test]
+    fn sign_and_verify() {
+        let signer = signer(1);
+        let (token, _) = signer.sign(3, 1, "Movie (2020)/movie.mp4", None);
+        assert_eq!(signer.verify(&token, 1, "Movie (2020)/movie.mp4", None), Some(3));
+        assert_eq!(signer.verify(&token, 1, "/Movie (2020)/movie.mp4/master.m3u8", None), Some(3));
+        assert_eq!(signer.verify(&token, 1, "Movie (2020)/movie.srt", None), None);
+        assert_eq!(signer.verify(&token, 1, "Movie (2020)", None), None);
+        assert_eq!(signer.verify(&token, 2, "Movie (2020)/movie.mp4", None), None);
+        let token2 = token.replacen(".3.", ".4.", 1);
+        assert_eq!(signer.verify(&token2, 1, "Movie (2020)/movie.mp4", None), None);
+        assert_eq!(self::signer(2).verify(&token, 1, "Movie (2020)/movie.mp4", None), None);
+    }
+
+    #[test]
+    fn bound_to_ip() {
+        let signer = signer(1);
+        let ip: IpAddr = "192.168.1.10".parse().unwrap();
+        let other: IpAddr = "192.168.1.11".parse().unwrap();
+        let (token, _) = signer.sign(3, 1, "movie.mp4", Some(ip));
+        assert_eq!(signer.verify(&token, 1, "movie.mp4", Some(ip)), Some(3));
+        assert_eq!(signer.verify(&token, 1, "movie.mp4", Some(other)), None);
+        assert_eq!(signer.verify(&token, 1, "movie.mp4", None), None);
+        let token2 = token.replacen(".1.", ".0.", 1);
+        assert_eq!(signer.verify(&token2, 1, "movie.mp4", Some(ip)), None);
+    }
+}
@@ -0,0 +1,232 @@
+//! Watch collections for changes with inotify.
+//!
+//! We watch the collection directory, every item directory, and the
+//! subdirectories of the item directories (e.g. seasons). Events are
+//! collected per item directory, and when things have been quiet for
+//! a little while, only the affected items are updated.
+//!
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
+use std::io;
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask, Watches};
+use tokio::time::{sleep_until, Instant};
+
+use crate::collections::Collection;
+use crate::config::Config;
+use crate::scanner::Scanner;
+
+// Wait until there have been no events for this long.
+const QUIET_TIME: Duration = Duration::from_secs(2);
+// But never wait longer than this.
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Start watching all collections that have `watch` enabled.
+pub fn start(config: &Config, scanner: &Scanner) {
+    for coll in config.collections.iter().filter(|c| c.watch) {
+        log::info!("watcher: watching {}", coll.directory);
+        let scanner = scanner.clone();
+        let coll = coll.clone();
+        tokio::spawn(async move {
+            if let Err(e) = watch(coll, scanner).await {
+                log::error!("watcher: {}", e);
+            }
+        });
+    }
+}
+
+fn mask() -> WatchMask {
+    WatchMask::CREATE
+        | WatchMask::DELETE
+        | WatchMask::CLOSE_WRITE
+        | WatchMask::MOVED_FROM
+        | WatchMask::MOVED_TO
+        | WatchMask::ATTRIB
+        | WatchMask::DELETE_SELF
+        | WatchMask::ONLYDIR
+}
+
+struct Watcher {
+    coll: Collection,
+    watches: Watches,
+    // Watch descriptor -> item directory, and true if it's a subdirectory.
+    // The collection directory itself is "".
+    wds: HashMap<WatchDescriptor, (String, bool)>,
+    // Failing to add a watch has been logged.
+    warned: bool,
+}
+
+impl Watcher {
+    // Watch a directory, and if it's an item directory, its subdirectories.
+    fn add(&mut self, item: &str, subdir: Option<&str>) {
+        let path = match subdir {
+            Some(subdir) => format!("{}/{}/{}", self.coll.directory, item, subdir),
+            None if item == "" => self.coll.directory.clone(),
+            None => format!("{}/{}", self.coll.directory, item),
+        };
+        match self.watches.add(&path, mask()) {
+            Ok(wd) => {
+                self.wds.insert(wd, (item.to_string(), subdir.is_some()));
+            },
+            // Usually ENOSPC, when fs.inotify.max_user_watches is too low.
+            Err(e) if e.kind() != io::ErrorKind::NotFound && !self.warned => {
+                log::warn!("watcher: {}: {} (further errors are logged at debug level)", path, e);
+                self.warned = true;
+                return;
+            },
+            Err(e) => {
+                log::debug!("watcher: {}: {}", path, e);
+                return;
+            },
+        }
+        if item == "" || subdir.is_some() {
+            return;
+        }
+        if let Ok(entries) = std::fs::read_dir(&path) {
+            for entry in entries.flatten() {
+                if entry.file_type().map(|t| t.is_dir()).unwrap_or(false) {
+                    if let Some(name) = visible(&entry.file_name()) {
+                        self.add(item, Some(name));
+                    }
+                }
+            }
+        }
+    }
+
+    // Watch all item directories in the collection directory.
+    fn add_items(&mut self) -> io::Result<()> {
+        for entry in std::fs::read_dir(&self.coll.directory)?.flatten() {
+            if entry.file_type().map(|t| t.is_dir()).unwrap_or(false) {
+                if let Some(name) = visible(&entry.file_name()) {
+                    self.add(name, None);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Stop watching an item directory and its subdirectories.
+    //
+    // A watch follows the directory when it is renamed, so we
+    // need to do this when an item directory is moved away.
+    fn remove(&mut self, item: &str) {
+        let wds = self.wds.iter().filter(|(_, v)| v.0 == item).map(|(k, _)| k.clone());
+        for wd in wds.collect::<Vec<_>>() {
+            self.wds.remove(&wd);
+            let _ = self.watches.remove(wd);
+        }
+    }
+}
+
+// Skip hidden files and directories, just like scandirs.
+fn visible(name: &OsStr) -> Option<&str> {
+    name.to_str().filter(|n| !n.starts_with(".") && !n.starts_with("+ "))
+}
+
+async fn watch(coll: Collection, scanner: Scanner) -> io::Result<()> {
+    let inotify = Inotify::init()?;
+    let mut stream = inotify.into_event_stream([0u8; 8192])?;
+    let mut w = Watcher {
+        coll,
+        watches: stream.watches(),
+        wds: HashMap::new(),
+        warned: false,
+    };
+
+    w.add("", None);
+    if w.wds.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, w.coll.directory.clone()));
+    }
+    w.add_items()?;
+
+    let mut dirty = HashSet::new();
+    let mut first = Instant::now();
+    let mut last = Instant::now();
+
+    loop {
+        let deadline = std::cmp::min(last + QUIET_TIME, first + MAX_DELAY);
+        let event = tokio::select! {
+            event = stream.next() => event,
+            _ = sleep_until(deadline), if !dirty.is_empty() => {
+                let dirs = dirty.iter().cloned().collect::<Vec<String>>();
+                match scanner.update_dirs(w.coll.collection_id, &dirs).await {
+                    Ok(true) => dirty.clear(),
+                    Ok(false) => {
+                        // A full scan is running, try again later.
+                        first = Instant::now();
+                        last = Instant::now();
+                    },
+                    Err(e) => {
+                        // Might be temporary (database busy), try again later.
+                        log::error!("watcher: {}: {}", w.coll.directory, e);
+                        first = Instant::now();
+                        last = Instant::now();
+                    },
+                }
+                continue;
+            },
+        };
+        let event = match event {
+            Some(event) => event?,
+            None => break,
+        };
+
+        if event.mask.contains(EventMask::Q_OVERFLOW) {
+            // Events were lost, so we don't know what changed. New directories
+            // might not be watched yet, and everything needs a rescan.
+            log::warn!("watcher: {}: event queue overflow, rescanning", w.coll.directory);
+            if let Err(e) = w.add_items() {
+                log::error!("watcher: {}: {}", w.coll.directory, e);
+            }
+            if !scanner.trigger(w.coll.collection_id) {
+                log::info!("watcher: {}: a scan is already running", w.coll.directory);
+            }
+            continue;
+        }
+        let (item, in_subdir) = match w.wds.get(&event.wd) {
+            Some(entry) => entry.clone(),
+            None => continue,
+        };
+        if event.mask.contains(EventMask::IGNORED) {
+            w.wds.remove(&event.wd);
+            continue;
+        }
+        let name = match event.name.as_deref().map(visible) {
+            Some(Some(name)) => name,
+            Some(None) => continue,
+            None => "",
+        };
+        let created = event.mask.intersects(EventMask::CREATE | EventMask::MOVED_TO);
+        let is_dir = event.mask.contains(EventMask::ISDIR);
+
+        let item = if item == "" {
+            // Event in the collection directory itself.
+            if !is_dir || name == "" {
+                continue;
+            }
+            if created {
+                w.add(name, None);
+            } else if event.mask.intersects(EventMask::DELETE | EventMask::MOVED_FROM) {
+                w.remove(name);
+            }
+            name.to_string()
+        } else {
+            // Event in an item directory, or one of its subdirectories.
+            if created && is_dir && !in_subdir && name != "" {
+                w.add(&item, Some(name));
+            }
+            item
+        };
+
+        log::trace!("watcher: {:?} in {}", event.mask, item);
+        if dirty.is_empty() {
+            first = Instant::now();
+        }
+        last = Instant::now();
+        dirty.insert(item);
+    }
+
+    Ok(())
+}